@@ -108,10 +108,61 @@ Mousetrap.bind('s', function() {
 });
 
 
+// toggle between the editor and the preview of the edited page, rendered by the server
+function togglePreview() {
+  const textarea = document.getElementById('source-editor');
+  const preview = document.getElementById('preview');
+  if (!textarea || !preview) {
+    return;
+  }
+  if (!preview.hidden) {
+    preview.hidden = true;
+    textarea.hidden = false;
+    textarea.focus();
+    return;
+  }
+  const formData = new URLSearchParams();
+  formData.append('body', textarea.value);
+  fetch('/_preview', {
+    method: 'POST',
+    body: formData,
+  }).then(function (response) {
+    if (!response.ok) {
+      throw new Error(response.statusText);
+    }
+    return response.text();
+  }).then(function (html) {
+    preview.innerHTML = html;
+    renderMath(preview);
+    preview.hidden = false;
+    textarea.hidden = true;
+    textarea.blur();
+  }).catch(function (error) {
+    alert('Preview failed: ' + error);
+  });
+}
+
+function isPreviewShown() {
+  const preview = document.getElementById('preview');
+  return preview && !preview.hidden;
+}
+
+Mousetrap.bindGlobal('alt+p', function() {
+  togglePreview();
+  return false;
+});
+
+Mousetrap.bind('p', function() {
+  togglePreview();
+});
+
 Mousetrap.bindGlobal('esc', function() {
   let textarea = document.getElementById('source-editor');
   let queryText = document.getElementById('query-text');
-  if (textarea && textarea === document.activeElement) {
+  if (isPreviewShown()) {
+    // back to editing, instead of leaving the editor
+    togglePreview();
+  } else if (textarea && textarea === document.activeElement) {
     textarea.blur();
     let button = document.getElementById('cancel-button');
     if (button) {
@@ -162,6 +213,332 @@ Mousetrap.bindGlobal(['alt+/', 'alt+f', 'alt+q'], function() {
   focusAppendToById('query-text');
 });
 
+function insertAtCursor(textarea, text) {
+  const start = textarea.selectionStart;
+  const end = textarea.selectionEnd;
+  textarea.value = textarea.value.substring(0, start) + text + textarea.value.substring(end);
+  textarea.selectionStart = textarea.selectionEnd = start + text.length;
+  textarea.focus();
+}
+
+// upload files as attachments of the edited page, and insert links to them
+function uploadAttachments(textarea, files) {
+  if (!files || files.length === 0) {
+    return;
+  }
+  const formData = new FormData();
+  for (var i = 0; i < files.length; i++) {
+    formData.append('file', files[i], files[i].name);
+  }
+  fetch('/_attachments/' + textarea.dataset.pageId + '/', {
+    method: 'POST',
+    headers: { 'Accept': 'text/plain' },
+    body: formData,
+  }).then(function (response) {
+    if (!response.ok) {
+      throw new Error(response.statusText);
+    }
+    return response.text();
+  }).then(function (links) {
+    insertAtCursor(textarea, links + '\n');
+  }).catch(function (error) {
+    alert('Uploading failed: ' + error);
+  });
+}
+
+(function () {
+  const button = document.getElementById('preview-button');
+  if (button) {
+    button.hidden = false;
+    button.addEventListener('click', togglePreview);
+  }
+})();
+
+(function () {
+  const textarea = document.getElementById('source-editor');
+  if (!textarea) {
+    return;
+  }
+  textarea.addEventListener('paste', function (e) {
+    if (e.clipboardData && e.clipboardData.files.length > 0) {
+      e.preventDefault();
+      uploadAttachments(textarea, e.clipboardData.files);
+    }
+  });
+  textarea.addEventListener('drop', function (e) {
+    if (e.dataTransfer && e.dataTransfer.files.length > 0) {
+      e.preventDefault();
+      uploadAttachments(textarea, e.dataTransfer.files);
+    }
+  });
+  const input = document.getElementById('attachment-input');
+  if (input) {
+    input.addEventListener('change', function () {
+      uploadAttachments(textarea, input.files);
+      input.value = '';
+    });
+  }
+})();
+
+// # Tag autocompletion
+//
+// Suggestions (from the server) for the word being typed in the query box,
+// or `#tag` being typed in the editor. `Tab` accepts the first one, `Esc` hides them.
+const tagSuggestions = {
+  elem: null,
+  input: null,
+  word: null,
+  suggestions: [],
+  requestId: 0,
+};
+
+function pathTags() {
+  return window.location.pathname.split('/')
+    .filter(function (s) { return s.length > 0; })
+    .map(decodeURIComponent);
+}
+
+function hideTagSuggestions() {
+  if (tagSuggestions.elem) {
+    tagSuggestions.elem.remove();
+    tagSuggestions.elem = null;
+  }
+}
+
+// the partially typed tag before the cursor: `{start, end, text}`, or null
+function wordBeforeCursor(input) {
+  const end = input.selectionStart;
+  if (end !== input.selectionEnd) {
+    return null;
+  }
+  const before = input.value.substring(0, end);
+  const re = input.tagName === 'TEXTAREA' ? /#([a-zA-Z0-9_\-]+)$/ : /([^\s]+)$/;
+  const match = before.match(re);
+  if (!match) {
+    return null;
+  }
+  return { start: end - match[1].length, end: end, text: match[1] };
+}
+
+function acceptTagSuggestion(tag) {
+  const input = tagSuggestions.input;
+  const word = tagSuggestions.word;
+  if (!input || !word) {
+    return;
+  }
+  const suffix = input.tagName === 'TEXTAREA' ? '' : ' ';
+  input.setSelectionRange(word.start, word.end);
+  insertAtCursor(input, tag + suffix);
+  hideTagSuggestions();
+}
+
+function showTagSuggestions(input, word, suggestions) {
+  hideTagSuggestions();
+  if (suggestions.length === 0 || (suggestions.length === 1 && suggestions[0].tag === word.text)) {
+    return;
+  }
+  const elem = document.createElement('div');
+  elem.id = 'tag-suggestions';
+  suggestions.forEach(function (suggestion) {
+    const button = document.createElement('button');
+    button.type = 'button';
+    button.className = 'pure-button';
+    button.textContent = suggestion.tag + ' (' + suggestion.count + ')';
+    button.tabIndex = -1;
+    // keep the focus in the input
+    button.addEventListener('mousedown', function (e) {
+      e.preventDefault();
+      acceptTagSuggestion(suggestion.tag);
+    });
+    elem.appendChild(button);
+  });
+  document.body.appendChild(elem);
+  tagSuggestions.elem = elem;
+  tagSuggestions.input = input;
+  tagSuggestions.word = word;
+  tagSuggestions.suggestions = suggestions;
+}
+
+function updateTagSuggestions(input) {
+  const word = wordBeforeCursor(input);
+  const requestId = ++tagSuggestions.requestId;
+  if (!word) {
+    hideTagSuggestions();
+    return;
+  }
+  const params = new URLSearchParams();
+  params.append('q', word.text);
+  params.append('context', pathTags().join(' '));
+  fetch('/_tags?' + params.toString()).then(function (response) {
+    return response.json();
+  }).then(function (suggestions) {
+    // ignore responses to outdated requests
+    if (requestId === tagSuggestions.requestId) {
+      showTagSuggestions(input, word, suggestions);
+    }
+  }).catch(function () {
+    hideTagSuggestions();
+  });
+}
+
+function enableTagSuggestions(input) {
+  if (!input) {
+    return;
+  }
+  input.addEventListener('input', function () {
+    updateTagSuggestions(input);
+  });
+  input.addEventListener('blur', hideTagSuggestions);
+  input.addEventListener('keydown', function (e) {
+    if (!tagSuggestions.elem || tagSuggestions.input !== input) {
+      return;
+    }
+    if (e.key === 'Tab') {
+      e.preventDefault();
+      acceptTagSuggestion(tagSuggestions.suggestions[0].tag);
+    } else if (e.key === 'Escape') {
+      // don't let Mousetrap leave the input
+      e.stopPropagation();
+      hideTagSuggestions();
+    }
+  });
+}
+
+enableTagSuggestions(document.getElementById('query-text'));
+enableTagSuggestions(document.getElementById('source-editor'));
+
+// # Drafts
+//
+// Edits are saved as drafts on the server a moment after typing stops,
+// so they can be restored if the tab is closed or saving fails.
+(function () {
+  const textarea = document.getElementById('source-editor');
+  if (!textarea || !textarea.dataset.pageId) {
+    return;
+  }
+  const url = '/_drafts/' + textarea.dataset.pageId;
+  var timer = null;
+  var submitting = false;
+
+  function saveDraft() {
+    timer = null;
+    if (submitting) {
+      return;
+    }
+    const formData = new URLSearchParams();
+    formData.append('body', textarea.value);
+    fetch(url, { method: 'POST', body: formData }).catch(function () {});
+  }
+
+  textarea.addEventListener('input', function () {
+    if (timer) {
+      window.clearTimeout(timer);
+    }
+    timer = window.setTimeout(saveDraft, 2000);
+  });
+  if (textarea.form) {
+    // saving the page discards the draft; don't recreate it afterwards
+    textarea.form.addEventListener('submit', function () {
+      submitting = true;
+      if (timer) {
+        window.clearTimeout(timer);
+        timer = null;
+      }
+    });
+  }
+  window.addEventListener('pagehide', function () {
+    if (timer && !submitting) {
+      window.clearTimeout(timer);
+      timer = null;
+      const formData = new URLSearchParams();
+      formData.append('body', textarea.value);
+      navigator.sendBeacon(url, formData);
+    }
+  });
+})();
+
+// # Task lists
+//
+// Checking a task list item in the page view saves the page with it toggled.
+// Each checkbox knows its page, so this works in embedded pages too.
+document.querySelectorAll('#page-content .task-checkbox').forEach(function (checkbox) {
+  checkbox.disabled = false;
+  checkbox.addEventListener('change', function () {
+    const formData = new URLSearchParams();
+    formData.append('id', checkbox.dataset.pageId);
+    formData.append('index', checkbox.dataset.task);
+    // so the wrong one isn't toggled, if the page was edited since
+    formData.append('text', checkbox.dataset.taskText);
+    formData.append('done', checkbox.checked);
+    checkbox.disabled = true;
+    fetch('/_tasks', { method: 'POST', body: formData }).then(function (response) {
+      if (!response.ok) {
+        throw new Error(response.statusText);
+      }
+    }).catch(function (error) {
+      checkbox.checked = !checkbox.checked;
+      alert('Saving failed: ' + error);
+    }).finally(function () {
+      checkbox.disabled = false;
+    });
+  });
+});
+
+// # Math
+//
+// TeX of `$...$` math is rendered to MathML by the bundled KaTeX,
+// loaded only on pages that have any.
+var katexLoading = null;
+// `document.currentScript` is only set while the script itself is running
+const katexUrl = document.currentScript.dataset.katexUrl;
+
+function renderMath(root) {
+  const elements = root.querySelectorAll('.math');
+  if (elements.length === 0) {
+    return;
+  }
+  if (!katexLoading) {
+    katexLoading = new Promise(function (resolve, reject) {
+      const script = document.createElement('script');
+      script.src = katexUrl;
+      script.onload = resolve;
+      script.onerror = reject;
+      document.head.appendChild(script);
+    });
+  }
+  katexLoading.then(function () {
+    elements.forEach(function (element) {
+      katex.render(element.textContent, element, {
+        displayMode: element.classList.contains('math-display'),
+        output: 'mathml',
+        throwOnError: false,
+      });
+    });
+  });
+}
+
+renderMath(document);
+
+// # Code blocks
+document.querySelectorAll('.code-block').forEach(function (block) {
+  const button = block.querySelector('.copy-button');
+  const code = block.querySelector('code');
+  if (!button || !code || !navigator.clipboard) {
+    return;
+  }
+  button.hidden = false;
+  button.addEventListener('click', function () {
+    navigator.clipboard.writeText(code.textContent).then(function () {
+      button.textContent = 'Copied';
+      window.setTimeout(function () {
+        button.textContent = 'Copy';
+      }, 1500);
+    }).catch(function (error) {
+      alert('Copying failed: ' + error);
+    });
+  });
+});
+
 // auto-select first element on index pages
 indexFocusSwitch(1);
 
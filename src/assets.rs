@@ -0,0 +1,145 @@
+//! Stylesheets and scripts served by tagwiki itself
+//!
+//! Their URLs (see `url`) contain a hash of the content, so browsers can cache
+//! them for good, and still get a new version as soon as it changes.
+
+use anyhow::{format_err, Result};
+use digest::Digest;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// `Cache-Control` of assets requested by their current hash
+pub const CACHE_FOREVER: &str = "public, max-age=31536000, immutable";
+
+/// `Cache-Control` of assets requested by an outdated (or no) hash
+pub const CACHE_REVALIDATE: &str = "no-cache";
+
+/// Stylesheet and script of a wiki (see `load_custom`)
+pub const CUSTOM_CSS: &str = "custom.css";
+pub const CUSTOM_JS: &str = "custom.js";
+
+#[derive(Debug)]
+pub struct Asset {
+    pub content_type: &'static str,
+    pub body: String,
+    pub hash: String,
+}
+
+impl Asset {
+    fn new(content_type: &'static str, body: String) -> Self {
+        let mut hasher = blake2::Blake2b::new();
+        hasher.update(body.as_bytes());
+        let hash = hex::encode(&hasher.finalize().as_slice()[0..8]);
+        Self {
+            content_type,
+            body,
+            hash,
+        }
+    }
+}
+
+/// All the assets, by name
+#[derive(Debug, Clone)]
+pub struct Assets(HashMap<String, Arc<Asset>>);
+
+impl Assets {
+    /// The ones bundled with tagwiki
+    pub fn builtin() -> Self {
+        Self(
+            vec![
+                (
+                    "pure-min.css",
+                    Asset::new(
+                        "text/css",
+                        include_str!("../resources/pure-min.css").to_owned(),
+                    ),
+                ),
+                (
+                    "grids-responsive-min.css",
+                    Asset::new(
+                        "text/css",
+                        include_str!("../resources/grids-responsive-min.css").to_owned(),
+                    ),
+                ),
+                (
+                    "style.css",
+                    Asset::new(
+                        "text/css",
+                        include_str!("../resources/style.css").to_owned(),
+                    ),
+                ),
+                (
+                    "script.js",
+                    Asset::new(
+                        "application/javascript",
+                        include_str!("../resources/mousetrap.min.js").to_owned()
+                            + include_str!("../resources/script.js"),
+                    ),
+                ),
+                (
+                    "katex.min.js",
+                    Asset::new(
+                        "application/javascript",
+                        include_str!("../resources/katex.min.js").to_owned(),
+                    ),
+                ),
+            ]
+            .into_iter()
+            .map(|(name, asset)| (name.to_owned(), Arc::new(asset)))
+            .collect(),
+        )
+    }
+
+    /// Add an asset generated at runtime (eg. from configuration)
+    pub fn register(&mut self, name: &str, content_type: &'static str, body: String) {
+        self.0
+            .insert(name.to_owned(), Arc::new(Asset::new(content_type, body)));
+    }
+
+    /// Register `_custom.css` and `_custom.js` of the wiki in `dir`, if it has them
+    ///
+    /// They are included in every page, after the built-in ones, so can override them.
+    pub fn load_custom(&mut self, dir: &Path) -> Result<()> {
+        for (name, content_type) in &[
+            (CUSTOM_CSS, "text/css"),
+            (CUSTOM_JS, "application/javascript"),
+        ] {
+            let path = dir.join(format!("_{}", name));
+            if !path.exists() {
+                continue;
+            }
+            let body = std::fs::read_to_string(&path)
+                .map_err(|e| format_err!("Can't read {}: {}", path.display(), e))?;
+            self.register(name, content_type, body);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Asset>> {
+        self.0.get(name).cloned()
+    }
+
+    /// URL of an asset, eg. `/_assets/0123456789abcdef/style.css`
+    pub fn url(&self, name: &str) -> String {
+        let hash = self
+            .get(name)
+            .map(|asset| asset.hash.clone())
+            .unwrap_or_else(|| "none".to_owned());
+        format!("/_assets/{}/{}", hash, name)
+    }
+}
+
+#[test]
+fn url_test() {
+    let mut assets = Assets::builtin();
+    let url = assets.url("style.css");
+    assert!(url.starts_with("/_assets/"));
+    assert!(url.ends_with("/style.css"));
+    assert_eq!(url.len(), "/_assets//style.css".len() + 16);
+
+    assets.register("test.css", "text/css", "a {}".into());
+    let first = assets.url("test.css");
+    assets.register("test.css", "text/css", "b {}".into());
+    assert_ne!(first, assets.url("test.css"));
+}
@@ -0,0 +1,158 @@
+//! Files attached to pages
+
+use crate::page::IdRef;
+use crate::util::check_path_component;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Directory (relative to the wiki root) attachments are kept in,
+/// in a separate sub-directory for every page ID
+pub const ATTACHMENTS_DIR: &str = ".attachments";
+
+/// Attachments stored in the wiki directory
+#[derive(Debug, Clone)]
+pub struct AttachmentStore {
+    root_path: PathBuf,
+}
+
+/// Turn a user-provided file name into one safe to keep on disk and use in URLs
+fn sanitize_file_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or("");
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '.' || ch == '-' || ch == '_' {
+                ch
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let name = name.trim_start_matches('.');
+
+    if name.is_empty() {
+        "attachment".into()
+    } else {
+        name.into()
+    }
+}
+
+/// URL under which an attachment is served
+pub fn url(id: IdRef, name: &str) -> String {
+    format!("/_attachments/{}/{}", id, name)
+}
+
+/// Markdown snippet linking (or embedding, for images) an attachment
+pub fn markdown_link(id: IdRef, name: &str) -> String {
+    let is_image = mime_guess::from_path(name)
+        .first()
+        .map(|mime| mime.type_() == mime_guess::mime::IMAGE)
+        .unwrap_or(false);
+    format!(
+        "{}[{}]({})",
+        if is_image { "!" } else { "" },
+        name,
+        url(id, name)
+    )
+}
+
+impl AttachmentStore {
+    pub fn new(wiki_root_path: &Path) -> Self {
+        Self {
+            root_path: wiki_root_path.join(ATTACHMENTS_DIR),
+        }
+    }
+
+    fn page_path(&self, id: IdRef) -> Result<PathBuf> {
+        check_path_component(id)?;
+        Ok(self.root_path.join(id))
+    }
+
+    /// Names of all files attached to a page
+    pub fn list(&self, id: IdRef) -> Result<Vec<String>> {
+        let page_path = self.page_path(id)?;
+        if !page_path.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut names = vec![];
+        for entry in std::fs::read_dir(page_path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // skip unfinished uploads
+            if entry.file_type()?.is_file() && !name.starts_with('.') {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn get(&self, id: IdRef, name: &str) -> Result<Vec<u8>> {
+        check_path_component(name)?;
+        Ok(std::fs::read(self.page_path(id)?.join(name))?)
+    }
+
+    /// Attach a file to a page
+    ///
+    /// Returns the name it was stored under, which might be different than `name`
+    /// to keep it safe and to avoid overwriting existing attachments.
+    pub fn put(&self, id: IdRef, name: &str, data: &[u8]) -> Result<String> {
+        let page_path = self.page_path(id)?;
+        std::fs::create_dir_all(&page_path)?;
+
+        let initial_name = sanitize_file_name(name);
+        let (stem, extension) = match initial_name.rfind('.') {
+            Some(i) if i > 0 => initial_name.split_at(i),
+            _ => (initial_name.as_str(), ""),
+        };
+        let mut name = initial_name.clone();
+        let mut i = 1;
+        while page_path.join(&name).exists() {
+            name = format!("{}-{}{}", stem, i, extension);
+            i += 1;
+        }
+
+        let path = page_path.join(&name);
+        let tmp_path = page_path.join(format!(".{}.tmp", crate::util::random_string(8)));
+        std::fs::write(&tmp_path, data)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(name)
+    }
+
+    /// IDs of all pages with attachments, with the last time any were added
+    pub fn page_ids(&self) -> Result<Vec<(String, std::time::SystemTime)>> {
+        if !self.root_path.exists() {
+            return Ok(vec![]);
+        }
+        let mut ids = vec![];
+        for entry in std::fs::read_dir(&self.root_path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                ids.push((
+                    entry.file_name().to_string_lossy().into_owned(),
+                    entry.metadata()?.modified()?,
+                ));
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Remove all files attached to a page
+    pub fn delete_all(&self, id: IdRef) -> Result<()> {
+        let page_path = self.page_path(id)?;
+        if page_path.exists() {
+            std::fs::remove_dir_all(page_path)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn sanitize_file_name_test() {
+    assert_eq!(sanitize_file_name("Screen Shot 1.png"), "Screen-Shot-1.png");
+    assert_eq!(sanitize_file_name("../../etc/passwd"), "passwd");
+    assert_eq!(sanitize_file_name(".hidden"), "hidden");
+    assert_eq!(sanitize_file_name(""), "attachment");
+}
@@ -11,4 +11,90 @@ pub struct Opts {
 
     #[structopt(long = "port", default_value = "3030")]
     pub port: u16,
+
+    /// Rename page files when their title changes
+    #[structopt(long = "rename-on-title-change")]
+    pub rename_on_title_change: bool,
+
+    /// Permanently remove pages that were in the trash for longer than this many days
+    #[structopt(long = "trash-retention-days")]
+    pub trash_retention_days: Option<i64>,
+
+    /// HTTP header with the name of the authenticated user (eg. `X-Forwarded-User`),
+    /// as set by an authenticating reverse proxy; used to record who edited pages
+    #[structopt(long = "author-header")]
+    pub author_header: Option<String>,
+
+    /// Tag of daily journal pages (can be given multiple times)
+    #[structopt(long = "journal-tag", default_value = "journal")]
+    pub journal_tags: Vec<String>,
+
+    /// File with the template of daily journal pages
+    #[structopt(long = "daily-note-template")]
+    pub daily_note_template: Option<PathBuf>,
+
+    /// Markdown extensions to enable: tables, tasklists, footnotes, strikethrough, math
+    /// (comma separated; empty to disable all)
+    #[structopt(
+        long = "markdown-extensions",
+        use_delimiter = true,
+        default_value = "tables,tasklists,footnotes,strikethrough,math"
+    )]
+    pub markdown_extensions: Vec<String>,
+
+    /// Color theme of highlighted code: InspiredGitHub, Solarized (light), Solarized (dark),
+    /// base16-ocean.light, base16-ocean.dark, base16-eighties.dark, base16-mocha.dark
+    #[structopt(long = "highlight-theme", default_value = crate::highlight::DEFAULT_THEME)]
+    pub highlight_theme: String,
+
+    /// Color theme of highlighted code when the browser prefers a dark color scheme;
+    /// empty to always use `--highlight-theme`
+    #[structopt(
+        long = "highlight-dark-theme",
+        default_value = crate::highlight::DEFAULT_DARK_THEME
+    )]
+    pub highlight_dark_theme: String,
+
+    /// Don't sanitize HTML of pages; only for wikis where everyone who can edit is trusted
+    #[structopt(long = "no-sanitize-html")]
+    pub no_sanitize_html: bool,
+
+    /// HTML tag to allow in pages, besides the default ones (can be given multiple times)
+    #[structopt(long = "allow-html-tag", number_of_values = 1)]
+    pub allow_html_tags: Vec<String>,
+
+    /// HTML attribute to allow on all tags (can be given multiple times)
+    #[structopt(long = "allow-html-attribute", number_of_values = 1)]
+    pub allow_html_attributes: Vec<String>,
+
+    /// Command rendering `dot` diagrams (Graphviz), eg. `dot`; disabled by default
+    #[structopt(long = "dot-command", default_value = "")]
+    pub dot_command: String,
+
+    /// Command rendering `mermaid` diagrams (mermaid-cli), eg. `mmdc`; disabled by default
+    #[structopt(long = "mermaid-command", default_value = "")]
+    pub mermaid_command: String,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+pub enum Command {
+    /// Print the path of today's journal page file, creating it if it doesn't exist
+    /// (only while the server is not running)
+    Today,
+    /// Append a timestamped line to a page
+    Append {
+        /// ID of the page
+        #[structopt(long = "id")]
+        id: Option<String>,
+
+        /// Tag of the page, if not given by ID; all tags must match exactly one page
+        #[structopt(long = "tag", short = "t", number_of_values = 1)]
+        tags: Vec<String>,
+
+        /// Text to append (read from stdin if not given)
+        text: Vec<String>,
+    },
 }
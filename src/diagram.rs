@@ -0,0 +1,222 @@
+//! `mermaid` and `dot` diagrams
+//!
+//! Fenced code blocks in these languages are rendered to SVG on the server,
+//! by local commands (`mmdc` from mermaid-cli, and `dot` from Graphviz).
+
+use anyhow::{bail, format_err, Result};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Maximum number of rendered diagrams kept around
+const CACHE_SIZE: usize = 1000;
+
+/// Diagrams with longer sources are not rendered
+const MAX_SOURCE_LEN: usize = 64 * 1024;
+
+/// Larger rendered diagrams are discarded
+const MAX_SVG_LEN: u64 = 4 * 1024 * 1024;
+
+/// Rendering commands taking longer get killed
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Commands rendering diagrams (none, if disabled)
+#[derive(Debug, Clone, Default)]
+pub struct Commands {
+    pub dot: Option<String>,
+    pub mermaid: Option<String>,
+}
+
+impl Commands {
+    /// Is code in `lang` a diagram that gets rendered
+    pub fn renders(&self, lang: &str) -> bool {
+        match lang {
+            "dot" => self.dot.is_some(),
+            "mermaid" => self.mermaid.is_some(),
+            _ => false,
+        }
+    }
+}
+
+lazy_static! {
+    // pages are rendered every time they are displayed, so rendering is cached
+    static ref CACHE: Mutex<HashMap<(String, String), String>> = Mutex::new(HashMap::new());
+}
+
+/// Read (up to `MAX_SVG_LEN` of) the output of a command in the background
+fn read_in_background(
+    output: impl Read + Send + 'static,
+) -> std::thread::JoinHandle<std::io::Result<Vec<u8>>> {
+    std::thread::spawn(move || {
+        let mut buf = vec![];
+        output.take(MAX_SVG_LEN + 1).read_to_end(&mut buf)?;
+        Ok(buf)
+    })
+}
+
+/// Run `program`, feeding it `source`, killing it after `TIMEOUT`
+///
+/// Blocks, so should not be called directly from async code.
+fn run(program: &str, args: &[&str], source: &str) -> Result<String> {
+    if MAX_SOURCE_LEN < source.len() {
+        bail!("Diagram too long ({} bytes)", source.len());
+    }
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format_err!("Can't run {}: {}", program, e))?;
+    let mut stdin = child.stdin.take().expect("piped");
+    let source = source.to_owned();
+    // the command might not read all of it, and that's its problem
+    std::thread::spawn(move || stdin.write_all(source.as_bytes()));
+    let stdout = read_in_background(child.stdout.take().expect("piped"));
+    let stderr = read_in_background(child.stderr.take().expect("piped"));
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if TIMEOUT < start.elapsed() {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("{} timed out", program);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let stdout = stdout.join().expect("not panicked")?;
+    let stderr = stderr.join().expect("not panicked")?;
+    if !status.success() {
+        bail!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&stderr).trim()
+        );
+    }
+    if MAX_SVG_LEN < stdout.len() as u64 {
+        bail!("{} output is too large", program);
+    }
+    let svg = String::from_utf8(stdout)?;
+    // skip XML declaration and doctype, to embed it in HTML
+    let start = svg
+        .find("<svg")
+        .ok_or_else(|| format_err!("{} didn't output SVG", program))?;
+    Ok(svg[start..].to_owned())
+}
+
+fn render_uncached(lang: &str, source: &str, commands: &Commands) -> Result<String> {
+    match lang {
+        "dot" => run(
+            commands
+                .dot
+                .as_deref()
+                .ok_or_else(|| format_err!("dot diagrams are disabled"))?,
+            &["-Tsvg"],
+            source,
+        ),
+        "mermaid" => run(
+            commands
+                .mermaid
+                .as_deref()
+                .ok_or_else(|| format_err!("mermaid diagrams are disabled"))?,
+            &["--input", "-", "--output", "-", "--outputFormat", "svg"],
+            source,
+        ),
+        _ => bail!("Unknown diagram language: {}", lang),
+    }
+}
+
+/// Placeholder of the `index`-th rendered diagram of a page
+///
+/// Data URLs would not make it through HTML sanitization, so diagrams are inserted after it.
+pub fn placeholder_html(lang: &str, index: usize) -> String {
+    format!(
+        r#"<div class="diagram diagram-{}" data-diagram="{}"></div>"#,
+        crate::util::escape_xml(lang),
+        index
+    )
+}
+
+/// `<img>` showing an SVG `diagram`
+///
+/// Scripts and links in an image are not run, so a diagram can't attack people
+/// reading the page (unlike an inline `<svg>`).
+fn img_html(svg: &str) -> String {
+    format!(
+        r#"<img src="data:image/svg+xml;base64,{}" alt="">"#,
+        base64::encode(svg)
+    )
+}
+
+/// Replace placeholders in `html` with rendered `diagrams`
+pub fn insert_rendered(html: &str, diagrams: &[String]) -> String {
+    lazy_static! {
+        static ref RE: regex::Regex =
+            regex::Regex::new(r#"(<div class="diagram [^"]*" data-diagram="(\d+)">)</div>"#)
+                .expect("correct regex");
+    }
+    if diagrams.is_empty() {
+        return html.to_owned();
+    }
+    RE.replace_all(html, |cap: &regex::Captures| {
+        let img = cap[2]
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| diagrams.get(index))
+            .map(|svg| img_html(svg))
+            .unwrap_or_default();
+        format!("{}{}</div>", &cap[1], img)
+    })
+    .into_owned()
+}
+
+/// Render a diagram in a given `lang`uage to SVG
+///
+/// Runs an external command, so blocks (for up to `TIMEOUT`).
+pub fn render(lang: &str, source: &str, commands: &Commands) -> Result<String> {
+    let key = (lang.to_owned(), source.to_owned());
+    if let Some(svg) = CACHE.lock().expect("not poisoned").get(&key) {
+        return Ok(svg.clone());
+    }
+    let svg = render_uncached(lang, source, commands)?;
+    let mut cache = CACHE.lock().expect("not poisoned");
+    if CACHE_SIZE <= cache.len() {
+        cache.clear();
+    }
+    cache.insert(key, svg.clone());
+    Ok(svg)
+}
+
+#[test]
+fn insert_rendered_test() {
+    let svg = r#"<svg><a href="javascript:alert(1)"><text>x</text></a></svg>"#;
+    let html = insert_rendered(
+        &format!("<p>a</p>{}", placeholder_html("dot", 0)),
+        &[svg.to_owned()],
+    );
+    assert!(!html.contains("<svg"));
+    assert!(!html.contains("javascript"));
+    assert_eq!(
+        html,
+        format!(
+            r#"<p>a</p><div class="diagram diagram-dot" data-diagram="0"><img src="data:image/svg+xml;base64,{}" alt=""></div>"#,
+            base64::encode(svg)
+        )
+    );
+}
+
+#[test]
+fn run_test() {
+    assert_eq!(
+        run("cat", &[], "<?xml?>\n<svg></svg>").unwrap(),
+        "<svg></svg>"
+    );
+    assert!(run("cat", &[], &"x".repeat(MAX_SOURCE_LEN + 1)).is_err());
+    assert!(run("false", &[], "").is_err());
+}
@@ -0,0 +1,150 @@
+//! Autosaved drafts of pages being edited
+
+use crate::util::check_path_component;
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+use std::path::{Path, PathBuf};
+
+/// Directory (relative to the wiki root) drafts are kept in
+pub const DRAFTS_DIR: &str = ".drafts";
+
+/// Unsaved content of the editor
+///
+/// Drafts are keyed by the ID of the edited page, or by the ID picked upfront
+/// for a new page (see `render::page_editing_view`).
+#[derive(Debug, Clone)]
+pub struct Draft {
+    pub key: String,
+    pub body: String,
+    pub time: DateTime<FixedOffset>,
+}
+
+impl Draft {
+    /// First non-empty line of the draft, to tell drafts apart
+    pub fn summary(&self) -> &str {
+        self.body
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or("(empty)")
+    }
+}
+
+/// Drafts stored in the wiki directory
+#[derive(Debug, Clone)]
+pub struct DraftStore {
+    root_path: PathBuf,
+}
+
+impl DraftStore {
+    pub fn new(wiki_root_path: &Path) -> Self {
+        Self {
+            root_path: wiki_root_path.join(DRAFTS_DIR),
+        }
+    }
+
+    fn draft_path(&self, key: &str) -> Result<PathBuf> {
+        check_path_component(key)?;
+        Ok(self.root_path.join(format!("{}.md", key)))
+    }
+
+    fn read(&self, key: &str, path: &Path) -> Result<Draft> {
+        let time: DateTime<chrono::Local> = std::fs::metadata(path)?.modified()?.into();
+        Ok(Draft {
+            key: key.to_owned(),
+            body: std::fs::read_to_string(path)?,
+            time: time.with_timezone(time.offset()),
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Draft>> {
+        let path = self.draft_path(key)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(self.read(key, &path)?))
+    }
+
+    /// All drafts, most recent first
+    pub fn list(&self) -> Result<Vec<Draft>> {
+        if !self.root_path.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut drafts = vec![];
+        for entry in std::fs::read_dir(&self.root_path)? {
+            let path = entry?.path();
+            let key = match path.file_stem() {
+                Some(stem) if path.extension().map(|ext| ext == "md").unwrap_or(false) => {
+                    stem.to_string_lossy().into_owned()
+                }
+                _ => continue,
+            };
+            // skip unfinished writes
+            if !key.starts_with('.') {
+                drafts.push(self.read(&key, &path)?);
+            }
+        }
+        drafts.sort_by_key(|draft| std::cmp::Reverse(draft.time));
+        Ok(drafts)
+    }
+
+    pub fn put(&self, key: &str, body: &str) -> Result<()> {
+        let path = self.draft_path(key)?;
+        std::fs::create_dir_all(&self.root_path)?;
+        let tmp_path = self
+            .root_path
+            .join(format!(".{}.tmp", crate::util::random_string(8)));
+        std::fs::write(&tmp_path, body)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn delete(&self, key: &str) -> Result<()> {
+        let path = self.draft_path(key)?;
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn draft_store_test() -> Result<()> {
+    let root_path =
+        std::env::temp_dir().join(format!("tagwiki-test-{}", crate::util::random_string(8)));
+    let store = DraftStore::new(&root_path);
+    assert!(store.list()?.is_empty());
+    assert!(store.get("abc")?.is_none());
+
+    store.put("abc", "\n  first line\nsecond line\n")?;
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    store.put("def", "")?;
+    let draft = store.get("abc")?.expect("saved");
+    assert_eq!(draft.body, "\n  first line\nsecond line\n");
+    assert_eq!(draft.summary(), "first line");
+    assert_eq!(store.get("def")?.expect("saved").summary(), "(empty)");
+
+    // unfinished writes are not drafts
+    std::fs::write(root_path.join(DRAFTS_DIR).join(".xyz.tmp"), "x")?;
+    std::fs::write(root_path.join(DRAFTS_DIR).join(".xyz.md"), "x")?;
+    let keys: Vec<_> = store.list()?.into_iter().map(|draft| draft.key).collect();
+    assert_eq!(keys, vec!["def", "abc"]);
+
+    store.put("abc", "changed")?;
+    assert_eq!(store.get("abc")?.expect("saved").body, "changed");
+    store.delete("abc")?;
+    store.delete("abc")?;
+    assert!(store.get("abc")?.is_none());
+    assert_eq!(store.list()?.len(), 1);
+
+    for key in &["", "..", "../abc", "a/b"] {
+        assert!(store.put(key, "x").is_err());
+        assert!(store.get(key).is_err());
+        assert!(store.delete(key).is_err());
+    }
+    assert!(!root_path.join("abc.md").exists());
+
+    std::fs::remove_dir_all(root_path)?;
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! Atom feeds of tag queries
+
+use crate::page;
+use crate::util::escape_xml;
+
+/// Render an Atom feed
+///
+/// `base_url` is the scheme and host (eg. `http://localhost:3030`) used to make links absolute,
+/// `path` is the tag path (eg. `/work/journal/`) the feed is about. `pages` come with their HTML.
+pub fn atom(base_url: &str, path: &str, title: &str, pages: &[(page::Parsed, String)]) -> String {
+    let updated = pages
+        .iter()
+        .map(|(page, _html)| page.headers.modification_time)
+        .max()
+        .unwrap_or_else(crate::util::now);
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    // page content uses server-relative links
+    out.push_str(&format!(
+        "<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:base=\"{}/\">\n",
+        escape_xml(base_url)
+    ));
+    out.push_str(&format!("<title>{}</title>\n", escape_xml(title)));
+    out.push_str(&format!(
+        "<id>{}</id>\n",
+        escape_xml(&(base_url.to_owned() + path))
+    ));
+    out.push_str(&format!(
+        "<link rel=\"self\" href=\"{}_feed.atom\"/>\n",
+        escape_xml(&(base_url.to_owned() + path))
+    ));
+    out.push_str(&format!(
+        "<link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n",
+        escape_xml(&(base_url.to_owned() + path))
+    ));
+    out.push_str(&format!("<updated>{}</updated>\n", updated.to_rfc3339()));
+    out.push_str("<author><name>tagwiki</name></author>\n");
+    out.push_str("<generator>tagwiki</generator>\n");
+
+    for (page, html) in pages {
+        out.push_str("<entry>\n");
+        out.push_str(&format!("<title>{}</title>\n", escape_xml(&page.title)));
+        // page IDs are stable across renames and moves, unlike page URLs
+        out.push_str(&format!("<id>urn:tagwiki:{}</id>\n", escape_xml(page.id())));
+        out.push_str(&format!(
+            "<link rel=\"alternate\" type=\"text/html\" href=\"{}/?id={}\"/>\n",
+            escape_xml(base_url),
+            escape_xml(page.id())
+        ));
+        out.push_str(&format!(
+            "<published>{}</published>\n",
+            page.headers.creation_time.to_rfc3339()
+        ));
+        out.push_str(&format!(
+            "<updated>{}</updated>\n",
+            page.headers.modification_time.to_rfc3339()
+        ));
+        if let Some(modified_by) = page.headers.modified_by.as_deref() {
+            out.push_str(&format!(
+                "<author><name>{}</name></author>\n",
+                escape_xml(modified_by)
+            ));
+        }
+        for tag in &page.tags {
+            out.push_str(&format!("<category term=\"{}\"/>\n", escape_xml(tag)));
+        }
+        out.push_str(&format!(
+            "<content type=\"html\">{}</content>\n",
+            escape_xml(html)
+        ));
+        out.push_str("</entry>\n");
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+#[test]
+fn atom_test() {
+    let page = page::Parsed::new_with_id("abc".into(), "# Q&A #journal\n\n<b>bold</b>\n");
+    let html = page.render(&page::RenderConfig::default()).html;
+    let feed = atom(
+        "http://localhost:3030",
+        "/journal/",
+        "journal",
+        &[(page, html)],
+    );
+
+    assert!(feed.contains("<title>Q&amp;A #journal</title>"));
+    assert!(feed.contains("<id>urn:tagwiki:abc</id>"));
+    assert!(feed.contains("href=\"http://localhost:3030/?id=abc\""));
+    assert!(feed.contains("<link rel=\"self\" href=\"http://localhost:3030/journal/_feed.atom\"/>"));
+    assert!(feed.contains("&lt;b&gt;bold&lt;/b&gt;"));
+    assert!(feed.contains("<category term=\"journal\"/>"));
+}
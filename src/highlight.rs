@@ -0,0 +1,174 @@
+//! Syntax highlighting of code blocks
+//!
+//! Code is marked up with CSS classes (prefixed with `hl-`), so the colors
+//! come from a stylesheet (see `theme_css`), that can be replaced or overriden.
+
+use crate::util::escape_xml;
+use anyhow::{format_err, Result};
+use lazy_static::lazy_static;
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag};
+use syntect::html::{ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// Color theme used, unless configured otherwise
+pub const DEFAULT_THEME: &str = "InspiredGitHub";
+
+/// Color theme used in dark mode, unless configured otherwise
+pub const DEFAULT_DARK_THEME: &str = "base16-ocean.dark";
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+lazy_static! {
+    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+}
+
+/// Names of the built-in color themes
+pub fn theme_names() -> Vec<String> {
+    syntect::highlighting::ThemeSet::load_defaults()
+        .themes
+        .keys()
+        .cloned()
+        .collect()
+}
+
+/// Stylesheet coloring highlighted code with a built-in color `theme`
+pub fn theme_css(theme: &str) -> Result<String> {
+    let themes = syntect::highlighting::ThemeSet::load_defaults();
+    let theme = themes.themes.get(theme).ok_or_else(|| {
+        format_err!(
+            "Unknown highlighting theme: {} (available: {})",
+            theme,
+            theme_names().join(", ")
+        )
+    })?;
+    Ok(syntect::html::css_for_theme_with_class_style(
+        theme,
+        CLASS_STYLE,
+    )?)
+}
+
+/// Like `theme_css`, but only applying when the browser prefers a dark color scheme
+pub fn dark_theme_css(theme: &str) -> Result<String> {
+    Ok(format!(
+        "@media (prefers-color-scheme: dark) {{\n{}}}\n",
+        theme_css(theme)?
+    ))
+}
+
+/// HTML of a code block: a diagram, if it is one, highlighted code otherwise
+///
+/// Rendered diagrams are added to `diagrams`, and only referred to by a placeholder
+/// (see `diagram::insert_rendered`).
+fn code_block_or_diagram_html(
+    lang: &str,
+    code: &str,
+    commands: &crate::diagram::Commands,
+    diagrams: &mut Vec<String>,
+) -> String {
+    if !commands.renders(lang) {
+        return code_block_html(lang, code);
+    }
+    match crate::diagram::render(lang, code, commands) {
+        Ok(svg) => {
+            diagrams.push(svg);
+            crate::diagram::placeholder_html(lang, diagrams.len() - 1)
+        }
+        // show the source, so it can still be read
+        Err(e) => format!(
+            r#"<div class="diagram-error">Rendering the diagram failed: {}</div>{}"#,
+            escape_xml(&e.to_string()),
+            code_block_html(lang, code)
+        ),
+    }
+}
+
+/// Highlighted HTML of `code` in a given `lang`uage (as in the info string of fenced code blocks)
+///
+/// Falls back to plain text for unknown languages.
+fn code_block_html(lang: &str, code: &str) -> String {
+    let highlighted = SYNTAX_SET
+        .find_syntax_by_token(lang)
+        .filter(|_| !lang.is_empty())
+        .and_then(|syntax| {
+            let mut generator =
+                ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAX_SET, CLASS_STYLE);
+            for line in LinesWithEndings::from(code) {
+                generator
+                    .parse_html_for_line_which_includes_newline(line)
+                    .ok()?;
+            }
+            Some(generator.finalize())
+        });
+
+    // the copy button is shown by javascript
+    format!(
+        r#"<div class="code-block"><button type="button" class="copy-button pure-button" hidden>Copy</button><pre class="hl-code"><code{}>{}</code></pre></div>"#,
+        if lang.is_empty() {
+            "".to_owned()
+        } else {
+            format!(r#" class="language-{}""#, escape_xml(lang))
+        },
+        highlighted.unwrap_or_else(|| escape_xml(code))
+    )
+}
+
+/// Replace code blocks in markdown events with highlighted HTML (or diagrams)
+pub fn highlight_events<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    commands: &crate::diagram::Commands,
+    diagrams: &mut Vec<String>,
+) -> Vec<Event<'a>> {
+    let mut out = vec![];
+    // language and code of the code block being collected
+    let mut code_block: Option<(String, String)> = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let lang = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().unwrap_or("").to_owned()
+                    }
+                    CodeBlockKind::Indented => "".to_owned(),
+                };
+                code_block = Some((lang, String::new()));
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some((lang, code)) = code_block.take() {
+                    out.push(Event::Html(CowStr::from(code_block_or_diagram_html(
+                        &lang, &code, commands, diagrams,
+                    ))));
+                }
+            }
+            Event::Text(text) if code_block.is_some() => {
+                if let Some((_, code)) = code_block.as_mut() {
+                    code.push_str(&text);
+                }
+            }
+            event => out.push(event),
+        }
+    }
+    out
+}
+
+#[test]
+fn highlight_events_test() {
+    let markdown = "```rust\nfn main() {}\n```\n\n    plain <code>\n";
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(
+        &mut html,
+        highlight_events(
+            pulldown_cmark::Parser::new(markdown),
+            &crate::diagram::Commands::default(),
+            &mut vec![],
+        )
+        .into_iter(),
+    );
+    assert!(html.contains(
+        r#"<pre class="hl-code"><code class="language-rust"><span class="hl-source hl-rust">"#
+    ));
+    assert!(html.contains(r#"<span class="hl-storage hl-type hl-function hl-rust">fn</span>"#));
+    assert!(html.contains("<pre class=\"hl-code\"><code>plain &lt;code&gt;\n</code></pre>"));
+    assert!(theme_css(DEFAULT_THEME).unwrap().contains(".hl-code"));
+}
@@ -3,8 +3,14 @@ use crate::page;
 use crate::page::{Id, Tag, TagRef};
 use anyhow::Result;
 use async_trait::async_trait;
+use lazy_static::lazy_static;
 use log::info;
-use std::collections::{HashMap, HashSet};
+use serde_derive::Deserialize;
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 /// Indexing wrapper over `page::Store`
 ///
@@ -14,7 +20,12 @@ use std::collections::{HashMap, HashSet};
 pub struct Index<T> {
     page_ids_by_tag: HashMap<String, HashSet<Id>>,
     tags_by_page_id: HashMap<Id, HashSet<Tag>>,
-    page_info_by_page_id: HashMap<Id, PageInfo>,
+    // `Arc`, so query results can share them, instead of cloning
+    page_info_by_page_id: HashMap<Id, Arc<PageInfo>>,
+    // keyed by `title_keys`, for `[[Page Title]]` links
+    page_ids_by_title: HashMap<String, HashSet<Id>>,
+    // only pages that have any
+    open_tasks_by_page_id: HashMap<Id, Vec<page::Task>>,
     store: T,
 }
 
@@ -24,12 +35,95 @@ pub struct PageInfo {
     pub id: Id,
     pub title: String,
     pub headers: page::Headers,
+    pub metadata: page::Metadata,
+}
+
+/// Query term filtering pages by their metadata, eg. `status:open` or `due<2026-11-01`
+#[derive(Debug, Clone)]
+struct MetadataFilter {
+    key: String,
+    // `:` is handled as "equal"
+    op: String,
+    value: String,
+}
+
+impl MetadataFilter {
+    fn parse(term: &str) -> Option<Self> {
+        lazy_static! {
+            static ref RE: regex::Regex =
+                regex::Regex::new(r"\A([a-zA-Z0-9_\-]+)(:|<=|>=|<|>)(.+)\z")
+                    .expect("correct regex");
+        }
+
+        RE.captures(term).map(|cap| MetadataFilter {
+            key: cap[1].to_lowercase(),
+            op: cap[2].to_owned(),
+            value: cap[3].to_owned(),
+        })
+    }
+
+    fn matches_value(&self, value: &page::MetadataValue) -> bool {
+        if let page::MetadataValue::List(values) = value {
+            return values.iter().any(|value| self.matches_value(value));
+        }
+
+        matches!(
+            (self.op.as_str(), value.partial_cmp_str(&self.value)),
+            (":", Some(Ordering::Equal))
+                | ("<", Some(Ordering::Less))
+                | (">", Some(Ordering::Greater))
+                | ("<=", Some(Ordering::Less))
+                | ("<=", Some(Ordering::Equal))
+                | (">=", Some(Ordering::Greater))
+                | (">=", Some(Ordering::Equal))
+        )
+    }
+
+    fn matches(&self, metadata: &page::Metadata) -> bool {
+        metadata
+            .get(&self.key)
+            .map(|value| self.matches_value(value))
+            .unwrap_or(false)
+    }
+}
+
+/// Order of pages in query results
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Newest first
+    #[default]
+    Created,
+    /// Recently modified first
+    Modified,
+    /// Alphabetically
+    Title,
+    /// Pages with fewest tags beyond the queried ones first
+    Relevance,
+}
+
+impl SortOrder {
+    pub const ALL: [SortOrder; 4] = [
+        SortOrder::Created,
+        SortOrder::Modified,
+        SortOrder::Title,
+        SortOrder::Relevance,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortOrder::Created => "created",
+            SortOrder::Modified => "modified",
+            SortOrder::Title => "title",
+            SortOrder::Relevance => "relevance",
+        }
+    }
 }
 
 /// Results of tag query lookup
 #[derive(Default, Debug, Clone)]
 pub struct FindResults {
-    pub matching_pages: Vec<PageInfo>,
+    pub matching_pages: Vec<Arc<PageInfo>>,
     pub matching_tags: Vec<Tag>,
 }
 
@@ -44,7 +138,7 @@ pub struct CompactResults {
     // all tags that were not already filtered on
     pub tags: Vec<(Tag, usize)>,
     // all pages that can't be reached by one of the `tags`
-    pub direct_hit_pages: Vec<PageInfo>,
+    pub direct_hit_pages: Vec<Arc<PageInfo>>,
 }
 
 impl<T> Index<T>
@@ -56,6 +150,8 @@ where
             page_ids_by_tag: Default::default(),
             tags_by_page_id: Default::default(),
             page_info_by_page_id: Default::default(),
+            page_ids_by_title: Default::default(),
+            open_tasks_by_page_id: Default::default(),
             store,
         };
 
@@ -89,7 +185,7 @@ where
             }
         }
 
-        let mut direct_hit_pages: Vec<PageInfo> = results
+        let mut direct_hit_pages: Vec<Arc<PageInfo>> = results
             .matching_pages
             .iter()
             .filter(|page_info| {
@@ -116,9 +212,168 @@ where
 }
 
 impl<T> Index<T> {
+    /// The indexed store
+    pub fn store(&self) -> &T {
+        &self.store
+    }
+
+    /// Does a page with a given `id` exist
+    pub fn contains(&self, id: page::IdRef) -> bool {
+        self.page_info_by_page_id.contains_key(id)
+    }
+
+    /// Page a `[[target]]` wiki link points to
+    ///
+    /// `target` is either `id:<page id>`, or a page title (case-insensitive, ignoring tags).
+    /// If multiple pages share a title, the most recently created one is used.
+    pub fn find_by_link_target(&self, target: &str) -> Option<Arc<PageInfo>> {
+        if let Some(id) = target.strip_prefix(crate::wikilink::ID_PREFIX) {
+            return self.page_info_by_page_id.get(id.trim()).cloned();
+        }
+        self.page_ids_by_title
+            .get(&title_keys(target)[0])?
+            .iter()
+            .filter_map(|id| self.page_info_by_page_id.get(id))
+            .max_by_key(|info| (info.headers.creation_time, info.id.clone()))
+            .cloned()
+    }
+
+    /// Unchecked task list items of pages with all the `tags`, newest pages first
+    pub fn open_tasks(&self, tags: &[TagRef]) -> Vec<(Arc<PageInfo>, Vec<page::Task>)> {
+        self.find(tags)
+            .matching_pages
+            .into_iter()
+            .filter_map(|info| {
+                let tasks = self.open_tasks_by_page_id.get(&info.id)?.clone();
+                Some((info, tasks))
+            })
+            .collect()
+    }
+
+    /// The latest page with all the `tags`, created on a given (local) `date`
+    pub fn find_created_on(
+        &self,
+        tags: &[TagRef],
+        date: chrono::NaiveDate,
+    ) -> Option<Arc<PageInfo>> {
+        let results = self.find(tags);
+        if results.matching_tags.len() != tags.iter().collect::<HashSet<_>>().len() {
+            return None;
+        }
+        // sorted newest first
+        results
+            .matching_pages
+            .into_iter()
+            .find(|info| crate::util::local_date(info.headers.creation_time) == date)
+    }
+
+    /// Existing tags matching a (partial) `query`, best first, with the number of pages using them
+    ///
+    /// Prefix matches go first, then substring matches, then fuzzy (subsequence) ones.
+    /// Within each of these, tags used together with all the `context_tags` on more pages
+    /// go first, then ones used on more pages overall.
+    pub fn suggest_tags(
+        &self,
+        query: &str,
+        context_tags: &[TagRef],
+        limit: usize,
+    ) -> Vec<(Tag, usize)> {
+        let query = query.trim().trim_start_matches('#').to_lowercase();
+        let is_subsequence = |tag: &str| {
+            let mut tag_chars = tag.chars();
+            query.chars().all(|ch| tag_chars.any(|tag_ch| tag_ch == ch))
+        };
+
+        let mut context_page_ids: Option<HashSet<&Id>> = None;
+        for tag in context_tags {
+            if let Some(ids) = self.page_ids_by_tag.get(*tag) {
+                context_page_ids = Some(match context_page_ids {
+                    None => ids.iter().collect(),
+                    Some(prev) => prev.into_iter().filter(|id| ids.contains(*id)).collect(),
+                });
+            }
+        }
+
+        let mut suggestions: Vec<_> = self
+            .page_ids_by_tag
+            .iter()
+            .filter(|(tag, _)| !context_tags.contains(&tag.as_str()))
+            .filter_map(|(tag, ids)| {
+                let match_rank = if tag.starts_with(&query) {
+                    0
+                } else if tag.contains(&query) {
+                    1
+                } else if is_subsequence(tag) {
+                    2
+                } else {
+                    return None;
+                };
+                let co_occurrence = context_page_ids
+                    .as_ref()
+                    .map(|context_ids| ids.iter().filter(|id| context_ids.contains(id)).count())
+                    .unwrap_or(0);
+                Some((match_rank, co_occurrence, tag, ids.len()))
+            })
+            .collect();
+        suggestions.sort_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then_with(|| b.1.cmp(&a.1))
+                .then_with(|| b.3.cmp(&a.3))
+                .then_with(|| a.2.cmp(b.2))
+        });
+        suggestions
+            .into_iter()
+            .take(limit)
+            .map(|(_, _, tag, count)| (tag.to_owned(), count))
+            .collect()
+    }
+
+    /// All template pages (see `template`), by title
+    pub fn templates(&self) -> Vec<Arc<PageInfo>> {
+        let mut templates: Vec<_> = self
+            .page_ids_by_tag
+            .get(crate::template::TEMPLATE_TAG)
+            .map(|ids| {
+                ids.iter()
+                    .map(|id| self.page_info_by_page_id[id].clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        templates.sort_by(|a, b| a.title.cmp(&b.title).then_with(|| a.id.cmp(&b.id)));
+        templates
+    }
+
+    /// Template page to apply to new pages with `tags`
+    ///
+    /// That is the one with most tags (besides the template tag), all of them in `tags`.
+    pub fn template_for(&self, tags: &[TagRef]) -> Option<Arc<PageInfo>> {
+        let template_tag = crate::template::TEMPLATE_TAG;
+        let (_, id) = self
+            .page_ids_by_tag
+            .get(template_tag)?
+            .iter()
+            .filter_map(|id| {
+                let other_tags: Vec<_> = self.tags_by_page_id[id]
+                    .iter()
+                    .filter(|tag| tag.as_str() != template_tag)
+                    .collect();
+                if !other_tags.is_empty()
+                    && other_tags.iter().all(|tag| tags.contains(&tag.as_str()))
+                {
+                    Some((other_tags.len(), id))
+                } else {
+                    None
+                }
+            })
+            .max_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(a.1)))?;
+        Some(self.page_info_by_page_id[id].clone())
+    }
+
     /// Lookup pages with a list of tags
+    ///
+    /// Besides tags, the list can contain metadata filters (see `MetadataFilter`).
     pub fn find(&self, tags: &[TagRef]) -> FindResults {
-        let mut matching_pages: Vec<PageInfo> = vec![];
+        let mut matching_pages: Vec<Arc<PageInfo>> = vec![];
         let mut matching_tags: Vec<String> = vec![];
         let mut already_tried_tags = HashSet::new();
 
@@ -126,55 +381,101 @@ impl<T> Index<T> {
             matching_pages = self
                 .tags_by_page_id
                 .keys()
-                .cloned()
-                .map(|id| self.page_info_by_page_id[&id].clone())
+                .map(|id| self.page_info_by_page_id[id].clone())
                 .collect();
         }
 
-        for tag in tags {
+        for &tag in tags {
             if already_tried_tags.contains(tag) {
                 continue;
             }
             already_tried_tags.insert(tag);
-            if matching_tags.is_empty() {
-                if let Some(ids) = &self.page_ids_by_tag.get(*tag) {
-                    matching_pages = ids
-                        .iter()
-                        .map(|id| self.page_info_by_page_id[id].clone())
-                        .collect();
-                    matching_tags.push(tag.to_string())
-                } else {
-                    return FindResults::empty();
-                }
-            } else {
-                if let Some(ids) = self.page_ids_by_tag.get(*tag) {
-                    let new_matching_pages: Vec<_> = matching_pages
-                        .iter()
-                        .filter(|info| ids.contains(info.id.as_str()))
-                        .map(|id| id.to_owned())
-                        .collect();
-                    if new_matching_pages.is_empty() {
-                        return FindResults {
-                            matching_pages,
-                            matching_tags,
+
+            let new_matching_pages: Vec<Arc<PageInfo>> =
+                if let Some(filter) = MetadataFilter::parse(tag) {
+                    let candidates: Box<dyn Iterator<Item = &Arc<PageInfo>>> =
+                        if matching_tags.is_empty() {
+                            Box::new(self.page_info_by_page_id.values())
+                        } else {
+                            Box::new(matching_pages.iter())
                         };
+                    candidates
+                        .filter(|info| filter.matches(&info.metadata))
+                        .cloned()
+                        .collect()
+                } else if let Some(ids) = self.page_ids_by_tag.get(tag) {
+                    if matching_tags.is_empty() {
+                        ids.iter()
+                            .map(|id| self.page_info_by_page_id[id].clone())
+                            .collect()
+                    } else {
+                        matching_pages
+                            .iter()
+                            .filter(|info| ids.contains(info.id.as_str()))
+                            .cloned()
+                            .collect()
                     }
-
-                    matching_pages = new_matching_pages;
-                    matching_tags.push(tag.to_string());
                 } else {
-                    return FindResults {
-                        matching_pages,
-                        matching_tags,
-                    };
+                    vec![]
+                };
+
+            if new_matching_pages.is_empty() {
+                if matching_tags.is_empty() {
+                    return FindResults::empty();
                 }
+                return FindResults {
+                    matching_pages,
+                    matching_tags,
+                };
             }
+
+            matching_pages = new_matching_pages;
+            matching_tags.push(tag.to_string());
         }
 
-        matching_pages.sort_unstable_by_key(|info| std::cmp::Reverse(info.headers.creation_time));
-        FindResults {
+        let mut results = FindResults {
             matching_pages,
             matching_tags,
+        };
+        self.sort_results(&mut results, SortOrder::default());
+        results
+    }
+
+    /// Sort pages of `results` in a given `order`
+    pub fn sort_results(&self, results: &mut FindResults, order: SortOrder) {
+        let pages = &mut results.matching_pages;
+        match order {
+            SortOrder::Created => {
+                pages.sort_unstable_by_key(|info| std::cmp::Reverse(info.headers.creation_time))
+            }
+            SortOrder::Modified => {
+                pages.sort_unstable_by_key(|info| std::cmp::Reverse(info.headers.modification_time))
+            }
+            SortOrder::Title => pages.sort_by(|a, b| {
+                a.title
+                    .to_lowercase()
+                    .cmp(&b.title.to_lowercase())
+                    .then_with(|| a.id.cmp(&b.id))
+            }),
+            SortOrder::Relevance => {
+                let matching_tags: HashSet<&str> =
+                    results.matching_tags.iter().map(String::as_str).collect();
+                pages.sort_by_cached_key(|info| {
+                    let other_tags_count = self
+                        .tags_by_page_id
+                        .get(&info.id)
+                        .map(|tags| {
+                            tags.iter()
+                                .filter(|tag| !matching_tags.contains(tag.as_str()))
+                                .count()
+                        })
+                        .unwrap_or(0);
+                    (
+                        other_tags_count,
+                        std::cmp::Reverse(info.headers.creation_time),
+                    )
+                })
+            }
         }
     }
 
@@ -187,13 +488,30 @@ impl<T> Index<T> {
         }
         self.tags_by_page_id
             .insert(page.id().to_owned(), page.tags.clone());
+        for key in title_keys(&page.title) {
+            self.page_ids_by_title
+                .entry(key)
+                .or_default()
+                .insert(page.id().to_owned());
+        }
+        let open_tasks: Vec<_> = page
+            .tasks
+            .iter()
+            .filter(|task| !task.done)
+            .cloned()
+            .collect();
+        if !open_tasks.is_empty() {
+            self.open_tasks_by_page_id
+                .insert(page.id().to_owned(), open_tasks);
+        }
         self.page_info_by_page_id.insert(
             page.id().to_owned(),
-            PageInfo {
+            Arc::new(PageInfo {
                 id: page.id().to_owned(),
                 title: page.title.clone(),
                 headers: page.headers.clone(),
-            },
+                metadata: page.headers.metadata(),
+            }),
         );
     }
 
@@ -202,15 +520,47 @@ impl<T> Index<T> {
             .tags_by_page_id
             .get(&id)
             .cloned()
-            .unwrap_or_else(|| HashSet::new())
+            .unwrap_or_else(HashSet::new)
         {
             self.page_ids_by_tag
                 .get_mut(&tag)
                 .map(|set| set.remove(&id));
         }
         self.tags_by_page_id.remove(&id);
-        self.page_info_by_page_id.remove(&id);
+        self.open_tasks_by_page_id.remove(&id);
+        if let Some(info) = self.page_info_by_page_id.remove(&id) {
+            for key in title_keys(&info.title) {
+                self.page_ids_by_title
+                    .get_mut(&key)
+                    .map(|set| set.remove(&id));
+            }
+        }
+    }
+}
+
+/// Normalized forms of a title, for looking up pages by title
+///
+/// The first one is the whole title, without `#` of tags. Titles often end with tags
+/// (`# Weekly meeting #work`), that links don't mention, so the second one skips them.
+fn title_keys(title: &str) -> Vec<String> {
+    let words: Vec<_> = title.split_whitespace().collect();
+    let mut keys = vec![words
+        .iter()
+        .map(|word| word.trim_start_matches('#'))
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()];
+    let without_tags = words
+        .iter()
+        .filter(|word| !(word.starts_with('#') && 1 < word.len()))
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    if !without_tags.is_empty() && without_tags != keys[0] {
+        keys.push(without_tags);
     }
+    keys
 }
 
 #[async_trait]
@@ -243,4 +593,182 @@ where
     async fn iter<'s>(&'s self) -> Result<Box<dyn Iterator<Item = Id> + 's>> {
         self.store.iter().await
     }
+
+    async fn trashed(&self) -> Result<Vec<page::store::Trashed>> {
+        self.store.trashed().await
+    }
+
+    async fn restore(&mut self, id: Id) -> Result<page::Parsed> {
+        let page = self.store.restore(id).await?;
+        self.add_data_for_page(&page);
+        Ok(page)
+    }
+
+    async fn purge(&mut self, id: Id) -> Result<()> {
+        self.store.purge(id).await
+    }
+}
+
+#[test]
+fn find_with_metadata_filters_test() {
+    let mut index = Index::<()>::default();
+    for (id, status, due) in &[
+        ("a", "open", "2026-10-01"),
+        ("b", "open", "2026-12-01"),
+        ("c", "done", "2026-10-01"),
+    ] {
+        let source = format!(
+            "<!---\ntagwiki-page-id: {}\nstatus: {}\ndue: {}\n-->\n#task",
+            id, status, due
+        );
+        index.add_data_for_page(&page::Parsed::from_full_source(source.into()));
+    }
+
+    let ids = |results: FindResults| {
+        let mut ids: Vec<_> = results
+            .matching_pages
+            .into_iter()
+            .map(|p| p.id.clone())
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    assert_eq!(ids(index.find(&["task", "status:open"])), vec!["a", "b"]);
+    assert_eq!(
+        ids(index.find(&["status:OPEN", "due<2026-11-01"])),
+        vec!["a"]
+    );
+    let results = index.find(&["task", "status:blocked"]);
+    assert_eq!(results.matching_tags, vec!["task"]);
+    assert_eq!(ids(results), vec!["a", "b", "c"]);
+}
+
+#[test]
+fn sort_results_test() {
+    let mut index = Index::<()>::default();
+    for (id, body) in &[
+        ("a", "# Beta #journal"),
+        ("b", "# alpha #journal #work"),
+        ("c", "# Gamma #journal"),
+    ] {
+        index.add_data_for_page(&page::Parsed::new_with_id(id.to_string(), body));
+    }
+
+    let ids = |results: &FindResults| -> Vec<String> {
+        results
+            .matching_pages
+            .iter()
+            .map(|p| p.id.clone())
+            .collect()
+    };
+
+    let mut results = index.find(&["journal"]);
+    index.sort_results(&mut results, SortOrder::Title);
+    assert_eq!(ids(&results), vec!["b", "a", "c"]);
+    index.sort_results(&mut results, SortOrder::Relevance);
+    assert_eq!(ids(&results)[2], "b");
+}
+
+#[test]
+fn template_for_test() {
+    let mut index = Index::<()>::default();
+    for (id, body) in &[
+        ("a", "# Notes #template #meeting"),
+        ("b", "# 1:1 #template #meeting #oneonone"),
+        ("c", "# Some meeting #meeting #oneonone"),
+    ] {
+        index.add_data_for_page(&page::Parsed::new_with_id(id.to_string(), body));
+    }
+
+    let template_id = |tags: &[TagRef]| index.template_for(tags).map(|info| info.id.clone());
+    assert_eq!(template_id(&["meeting"]).as_deref(), Some("a"));
+    assert_eq!(template_id(&["oneonone", "meeting"]).as_deref(), Some("b"));
+    assert_eq!(template_id(&["journal"]), None);
+    assert_eq!(index.templates().len(), 2);
+}
+
+#[test]
+fn open_tasks_test() {
+    let mut index = Index::<()>::default();
+    for (id, body) in &[
+        ("a", "# A #work\n\n- [ ] one\n- [x] two\n- [ ] three"),
+        ("b", "# B #work\n\n- [x] done"),
+        ("c", "# C #home\n\n- [ ] four"),
+    ] {
+        index.add_data_for_page(&page::Parsed::new_with_id(id.to_string(), body));
+    }
+    index.clean_data_for_page("c".into());
+
+    let open_tasks = |tags: &[TagRef]| {
+        index
+            .open_tasks(tags)
+            .into_iter()
+            .map(|(info, tasks)| {
+                (
+                    info.id.clone(),
+                    tasks.into_iter().map(|task| task.index).collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(open_tasks(&["work"]), vec![("a".to_owned(), vec![0, 2])]);
+    assert_eq!(open_tasks(&[]), vec![("a".to_owned(), vec![0, 2])]);
+}
+
+#[test]
+fn find_by_link_target_test() {
+    let mut index = Index::<()>::default();
+    for (id, body) in &[
+        ("a", "# Weekly meeting #work"),
+        ("b", "# #Tagwiki #trash"),
+        ("c", "# Old title"),
+    ] {
+        index.add_data_for_page(&page::Parsed::new_with_id(id.to_string(), body));
+    }
+    index.clean_data_for_page("c".into());
+    index.add_data_for_page(&page::Parsed::new_with_id("c".into(), "# New title"));
+
+    let find = |target: &str| {
+        index
+            .find_by_link_target(target)
+            .map(|info| info.id.clone())
+    };
+    assert_eq!(find("weekly  Meeting").as_deref(), Some("a"));
+    assert_eq!(find("Weekly meeting #work").as_deref(), Some("a"));
+    assert_eq!(find("Tagwiki trash").as_deref(), Some("b"));
+    assert_eq!(find("id:b").as_deref(), Some("b"));
+    assert_eq!(find("New title").as_deref(), Some("c"));
+    assert_eq!(find("Old title"), None);
+    assert_eq!(find("id:x"), None);
+}
+
+#[test]
+fn suggest_tags_test() {
+    let mut index = Index::<()>::default();
+    for (id, body) in &[
+        ("a", "#work #meeting"),
+        ("b", "#work #meeting"),
+        ("e", "#work #meetup"),
+        ("c", "#home #meetup"),
+        ("d", "#home #meetup #mtg"),
+    ] {
+        index.add_data_for_page(&page::Parsed::new_with_id(id.to_string(), body));
+    }
+
+    let suggestions = |query: &str, context: &[TagRef]| -> Vec<String> {
+        index
+            .suggest_tags(query, context, 10)
+            .into_iter()
+            .map(|(tag, _)| tag)
+            .collect()
+    };
+    // more popular first
+    assert_eq!(suggestions("#mee", &[]), vec!["meetup", "meeting"]);
+    // unless used together with the context tags
+    assert_eq!(suggestions("mee", &["work"]), vec!["meeting", "meetup"]);
+    // prefix matches, then substring ones, then fuzzy ones
+    assert_eq!(suggestions("m", &["home"])[..2], ["meetup", "mtg"]);
+    assert_eq!(suggestions("mtg", &[]), vec!["mtg", "meeting"]);
+    assert_eq!(suggestions("mtp", &[]), vec!["meetup"]);
 }
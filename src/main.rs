@@ -1,19 +1,42 @@
 //! tagwiki
 
 use anyhow::{bail, format_err, Result};
+use bytes::Buf;
+use chrono::Datelike;
+use futures::TryStreamExt;
 use log::info;
 use std::sync::Arc;
 use structopt::StructOpt;
 use warp::{path::FullPath, Filter};
 
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 
 use page::StoreMut;
 
+/// Stylesheets and scripts
+mod assets;
+/// Files attached to pages
+mod attachment;
 /// Command line options
 mod cli;
+/// Rendering diagrams
+mod diagram;
+/// Autosaved drafts
+mod draft;
+/// Atom feeds
+mod feed;
+/// Syntax highlighting
+mod highlight;
+/// Math
+mod math;
 /// Page
 mod page;
+/// HTML sanitization
+mod sanitize;
+/// Templates of new pages
+mod template;
+/// `[[Page Title]]` links and embeds
+mod wikilink;
 
 mod index;
 
@@ -22,8 +45,14 @@ mod util;
 
 mod render;
 
+/// Maximum size of a single attachment upload request
+const MAX_ATTACHMENT_UPLOAD_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Age after which attachments of pages that don't exist are removed
+const ORPHANED_ATTACHMENTS_AGE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
 #[derive(Debug)]
-struct RejectAnyhow(anyhow::Error);
+struct RejectAnyhow(#[allow(dead_code)] anyhow::Error);
 
 impl warp::reject::Reject for RejectAnyhow {}
 
@@ -31,6 +60,50 @@ impl warp::reject::Reject for RejectAnyhow {}
 struct State {
     page_store:
         Arc<tokio::sync::RwLock<index::Index<Box<dyn page::store::StoreMut + Sync + Send>>>>,
+    attachments: attachment::AttachmentStore,
+    drafts: draft::DraftStore,
+    author_header: Option<String>,
+    journal_tags: Vec<String>,
+    daily_note_template: String,
+    render_config: page::RenderConfig,
+    layout: render::Layout,
+}
+
+/// Number of pages displayed in the recent changes view
+const RECENT_CHANGES_COUNT: usize = 100;
+
+/// Maximum number of tags suggested at once
+const TAG_SUGGESTION_COUNT: usize = 10;
+
+/// Number of (most recent) pages included in a feed
+const FEED_ENTRY_COUNT: usize = 50;
+
+/// Last path segment of feed URLs, eg. `/work/journal/_feed.atom`
+const FEED_PATH_SUFFIX: &str = "/_feed.atom";
+
+/// Last path segment of the quick-capture URLs, eg. `/inbox/_append`
+const APPEND_PATH_SUFFIX: &str = "/_append";
+
+/// File of a wiki with its own template of the HTML around every page
+const SHELL_TEMPLATE_FILE: &str = "_shell.html";
+
+/// Last path segment of open task list URLs, eg. `/work/_tasks`
+const TASKS_PATH_SUFFIX: &str = "/_tasks";
+
+/// File of a wiki with the port of the server running on it (see `check_server_not_running`)
+const SERVER_PORT_FILE: &str = ".tagwiki-port";
+
+impl State {
+    /// Name of the user making a request, if known
+    fn request_author(&self, headers: &warp::http::HeaderMap) -> Option<String> {
+        let value = headers.get(self.author_header.as_deref()?)?.to_str().ok()?;
+        let value = value.trim();
+        if value.is_empty() {
+            None
+        } else {
+            Some(value.to_owned())
+        }
+    }
 }
 
 fn with_state(
@@ -64,15 +137,73 @@ struct GetParams {
     edit: Option<bool>,
     id: Option<String>,
     q: Option<String>,
+    sort: Option<index::SortOrder>,
+    // 1-based page of a page listing
+    page: Option<usize>,
+    // month (`YYYY-MM`, current one if empty) to display the calendar of
+    calendar: Option<String>,
+    // open (or create) the page created today
+    today: Option<bool>,
+    // ID of the template page to create a new page from (none, if empty)
+    template: Option<String>,
+    // key of the draft to restore into the editor
+    draft: Option<String>,
+    // title of the new page, when following a dangling `[[Page Title]]` link
+    title: Option<String>,
+    // tags (space separated) dropped from the path, as no page had all of them
+    dropped: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LinkParams {
+    // target of a `[[target]]` link
+    to: String,
 }
 
 #[derive(Deserialize, Debug)]
 struct PostForm {
     body: Option<String>,
     id: Option<String>,
+    // ID to use for a newly created page
+    new_id: Option<String>,
+    _method: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TagSuggestionParams {
+    q: Option<String>,
+    // space separated tags the suggested ones are going to be used with
+    context: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct TagSuggestion {
+    tag: String,
+    count: usize,
+}
+
+#[derive(Deserialize, Debug)]
+struct DraftForm {
+    body: Option<String>,
     _method: Option<String>,
 }
 
+#[derive(Deserialize, Debug)]
+struct TaskForm {
+    id: String,
+    // index of the task list item in the page
+    index: usize,
+    // its text, as displayed when it was clicked
+    text: String,
+    done: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct AppendForm {
+    id: Option<String>,
+    text: String,
+}
+
 impl PostForm {
     fn get_body(&self) -> Result<&str> {
         self.body
@@ -88,58 +219,115 @@ fn warp_reply_from_render(render: impl horrorshow::RenderOnce) -> Box<dyn warp::
     ))
 }
 
-fn path_to_tags(path: &FullPath) -> Vec<&str> {
-    path.as_str()
-        .split('/')
-        .map(|t| t.trim())
-        .filter(|t| t != &"")
+/// Tags (and metadata filters) of the path, eg. `/work/status:open/due%3C2026-11-01/`
+fn path_to_tags(path: &FullPath) -> Vec<String> {
+    path_str_to_tags(path.as_str())
+}
+
+fn path_str_to_tags(path: &str) -> Vec<String> {
+    path.split('/')
+        .map(|t| util::decode_path_segment(t.trim()))
+        .filter(|t| !t.is_empty())
         .collect()
 }
 
-async fn handle_style_css() -> std::result::Result<warp::http::Response<String>, warp::Rejection> {
-    Ok(warp::http::Response::builder()
-        .status(200)
-        .header(warp::http::header::CONTENT_TYPE, "text/css")
-        .body(
-            // include_str!("../resources/reset.css").to_string()
-            include_str!("../resources/style.css").to_string(),
-        )
-        .expect("correct redirect"))
+fn tags_to_path(tags: &[impl AsRef<str>]) -> String {
+    "/".to_string()
+        + &tags
+            .iter()
+            .map(|tag| util::encode_path_segment(tag.as_ref()))
+            .collect::<Vec<_>>()
+            .join("/")
+}
+
+/// The only page matching all the `tags`
+///
+/// Returns `None` if there is more than one.
+fn single_matching_page_id<T>(index: &index::Index<T>, tags: &[&str]) -> Result<Option<page::Id>> {
+    let results = index.find(tags);
+    if results.matching_tags != tags {
+        bail!("Page not found");
+    }
+    match results.matching_pages.len() {
+        1 => Ok(Some(results.matching_pages[0].id.clone())),
+        0 => bail!("Page not found"),
+        _ => Ok(None),
+    }
+}
+
+/// Append a timestamped line to the page with a given `id`, or the single page with all the `tags`
+async fn append_to_page<T>(
+    index: &mut index::Index<T>,
+    id: Option<String>,
+    tags: &[&str],
+    text: &str,
+    author: Option<String>,
+) -> Result<page::Id>
+where
+    T: page::StoreMut + Send + Sync,
+{
+    if text.trim().is_empty() {
+        bail!("Nothing to append");
+    }
+    let id = if let Some(id) = id {
+        id
+    } else {
+        single_matching_page_id(index, tags)?
+            .ok_or_else(|| format_err!("More than one page matches"))?
+    };
+    let old_page = index.get(id.clone()).await?;
+    let mut page = old_page.with_appended_entry(util::now(), text);
+    page.record_modification(
+        author,
+        util::line_diff_summary(&old_page.source_body, &page.source_body),
+    );
+    index.put(&page).await?;
+    Ok(id)
 }
 
-async fn handle_script_js() -> std::result::Result<warp::http::Response<String>, warp::Rejection> {
+/// Serve a bundled asset (see `assets::url`)
+///
+/// Only requests with the current hash of the asset get cached by browsers indefinitely.
+async fn handle_asset(
+    state: Arc<State>,
+    hash: String,
+    name: String,
+) -> std::result::Result<warp::http::Response<String>, warp::Rejection> {
+    let asset = state
+        .layout
+        .assets
+        .get(&name)
+        .ok_or_else(warp::reject::not_found)?;
+    let cache_control = if hash == asset.hash {
+        assets::CACHE_FOREVER
+    } else {
+        assets::CACHE_REVALIDATE
+    };
     Ok(warp::http::Response::builder()
         .status(200)
-        .header(warp::http::header::CONTENT_TYPE, "application/javascript")
-        .body(
-            include_str!("../resources/mousetrap.min.js").to_string()
-                + include_str!("../resources/script.js"),
-        )
+        .header(warp::http::header::CONTENT_TYPE, asset.content_type)
+        .header(warp::http::header::CACHE_CONTROL, cache_control)
+        .body(asset.body.clone())
         .expect("correct response"))
 }
 
 async fn handle_query(
     query: GetParams,
 ) -> std::result::Result<warp::http::Response<&'static str>, warp::Rejection> {
-    let q = "/".to_string()
-        + &query
-            .q
-            .unwrap_or_else(|| String::new())
-            .split(" ")
-            .filter(|s| !s.is_empty())
-            .collect::<Vec<_>>()
-            .join("/");
-    Ok(warp_temporary_redirect_to_get_method(&q))
+    let q = query.q.unwrap_or_default();
+    let tags = q.split(' ').filter(|s| !s.is_empty()).collect::<Vec<_>>();
+    Ok(warp_temporary_redirect_to_get_method(&tags_to_path(&tags)))
 }
 
 async fn handle_post_wrapped(
     state: Arc<State>,
     path: FullPath,
+    headers: warp::http::HeaderMap,
     form: PostForm,
 ) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
     if let Some("put") = form._method.as_deref() {
         // workaround for not being able to use `method="put"` in html forms
-        handle_put(state, path, form)
+        handle_put(state, path, headers, form)
             .await
             .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
     } else if let Some("delete") = form._method.as_deref() {
@@ -147,7 +335,7 @@ async fn handle_post_wrapped(
             .await
             .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
     } else {
-        handle_post(state, path, form)
+        handle_post(state, path, headers, form)
             .await
             .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
     }
@@ -156,28 +344,31 @@ async fn handle_post_wrapped(
 async fn handle_post(
     state: Arc<State>,
     path: FullPath,
+    headers: warp::http::HeaderMap,
     form: PostForm,
 ) -> Result<Box<dyn warp::Reply>> {
     let tags = path_to_tags(&path);
+    let tags: Vec<_> = tags.iter().map(String::as_str).collect();
     let mut write = state.page_store.write().await;
 
     let post_id = if let Some(id) = form.id.as_deref() {
         id.to_owned()
+    } else if let Some(id) = single_matching_page_id(&write, &tags)? {
+        id
     } else {
-        let results = write.find(tags.as_slice());
-        match results.matching_pages.len() {
-            1 => results.matching_pages[0].id.clone(),
-            0 => bail!("Page not found"),
-            _ => return Ok(Box::new(warp_temporary_redirect_to_get_method(".".into()))),
-        }
+        return Ok(Box::new(warp_temporary_redirect_to_get_method(".")));
     };
-    let page = write.get(post_id.to_owned()).await?;
+    let old_page = write.get(post_id.to_owned()).await?;
 
     let mut page =
-        page.with_new_source_body(&get_rid_of_windows_newlines(form.get_body()?.to_owned()));
-    page.update_modification_time();
+        old_page.with_new_source_body(&get_rid_of_windows_newlines(form.get_body()?.to_owned()));
+    page.record_modification(
+        state.request_author(&headers),
+        util::line_diff_summary(&old_page.source_body, &page.source_body),
+    );
 
     write.put(&page).await?;
+    state.drafts.delete(&post_id)?;
 
     Ok(Box::new(warp_temporary_redirect_to_get_method(&format!(
         "?id={}",
@@ -188,20 +379,38 @@ async fn handle_post(
 async fn handle_put_wrapped(
     state: Arc<State>,
     path: FullPath,
+    headers: warp::http::HeaderMap,
     form: PostForm,
 ) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
-    handle_put(state, path, form)
+    handle_put(state, path, headers, form)
         .await
         .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
 }
 async fn handle_put(
     state: Arc<State>,
     _path: FullPath,
+    headers: warp::http::HeaderMap,
     form: PostForm,
 ) -> Result<Box<dyn warp::Reply>> {
-    let page = page::Parsed::new(&get_rid_of_windows_newlines(form.get_body()?.to_owned()));
+    let body = get_rid_of_windows_newlines(form.get_body()?.to_owned());
     let mut write = state.page_store.write().await;
+    // the ID comes from the client, and must not overwrite an existing page; nor can another
+    // one be picked instead, as files might already be attached under it
+    let mut page = match form.new_id {
+        Some(new_id) => {
+            if !page::Parsed::is_valid_new_id(&new_id) {
+                bail!("Invalid page ID: {}", new_id);
+            }
+            if write.contains(&new_id) {
+                bail!("Page {} exists already", new_id);
+            }
+            page::Parsed::new_with_id(new_id, &body)
+        }
+        None => page::Parsed::new(&body),
+    };
+    page.record_modification(state.request_author(&headers), "created".into());
     write.put(&page).await?;
+    state.drafts.delete(page.id())?;
 
     Ok(Box::new(warp_temporary_redirect_to_get_method(&format!(
         "?id={}",
@@ -229,18 +438,467 @@ async fn handle_delete(
         .await?;
     write.delete(page.id().to_owned()).await?;
 
-    Ok(Box::new(warp_temporary_redirect_to_get_method(&format!(
-        ".",
+    Ok(Box::new(warp_temporary_redirect_to_get_method(".")))
+}
+
+async fn handle_today(state: Arc<State>) -> std::result::Result<impl warp::Reply, warp::Rejection> {
+    let path = tags_to_path(&state.journal_tags);
+    let path = if path.ends_with('/') {
+        path
+    } else {
+        path + "/"
+    };
+    Ok(warp_temporary_redirect(&format!("{}?today=true", path)))
+}
+
+async fn handle_append_wrapped(
+    state: Arc<State>,
+    tags_path: String,
+    headers: warp::http::HeaderMap,
+    form: AppendForm,
+) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
+    handle_append(state, tags_path, headers, form)
+        .await
+        .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
+}
+
+async fn handle_append(
+    state: Arc<State>,
+    tags_path: String,
+    headers: warp::http::HeaderMap,
+    form: AppendForm,
+) -> Result<Box<dyn warp::Reply>> {
+    let tags = path_str_to_tags(&tags_path);
+    let tags: Vec<_> = tags.iter().map(String::as_str).collect();
+    let mut write = state.page_store.write().await;
+    let id = append_to_page(
+        &mut write,
+        form.id,
+        &tags,
+        &get_rid_of_windows_newlines(form.text),
+        state.request_author(&headers),
+    )
+    .await?;
+
+    let accept = headers
+        .get(warp::http::header::ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    if accept == Some("text/plain") {
+        Ok(Box::new(id))
+    } else {
+        Ok(Box::new(warp_temporary_redirect_to_get_method(&format!(
+            "{}?id={}",
+            tags_path, id
+        ))))
+    }
+}
+
+async fn handle_preview_wrapped(
+    state: Arc<State>,
+    form: PostForm,
+) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
+    handle_preview(state, form)
+        .await
+        .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
+}
+
+/// Render page body the same way it would be rendered once saved
+async fn handle_preview(state: Arc<State>, form: PostForm) -> Result<Box<dyn warp::Reply>> {
+    let body = get_rid_of_windows_newlines(form.get_body()?.to_owned());
+    let page = page::Parsed::new(&body);
+    let read = state.page_store.read().await;
+    Ok(Box::new(warp::reply::html(
+        wikilink::resolve(&read, &page, &state.render_config)
+            .await?
+            .html,
+    )))
+}
+
+async fn handle_link_wrapped(
+    state: Arc<State>,
+    query: LinkParams,
+) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
+    handle_link(state, query)
+        .await
+        .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
+}
+
+/// Follow a `[[target]]` link: go to the page, or create it if it doesn't exist
+async fn handle_link(state: Arc<State>, query: LinkParams) -> Result<Box<dyn warp::Reply>> {
+    let read = state.page_store.read().await;
+    if let Some(info) = read.find_by_link_target(&query.to) {
+        return Ok(Box::new(warp_temporary_redirect(&format!(
+            "/?id={}",
+            info.id
+        ))));
+    }
+    if query.to.starts_with(wikilink::ID_PREFIX) {
+        bail!("Page not found: {}", query.to);
+    }
+    Ok(Box::new(warp_temporary_redirect(&format!(
+        "/?edit=true&title={}",
+        percent_encoding::utf8_percent_encode(query.to.trim(), percent_encoding::NON_ALPHANUMERIC)
     ))))
 }
 
+async fn handle_tag_suggestions(
+    state: Arc<State>,
+    query: TagSuggestionParams,
+) -> std::result::Result<impl warp::Reply, warp::Rejection> {
+    let context = query.context.unwrap_or_default();
+    let context_tags: Vec<_> = context.split_whitespace().collect();
+    let read = state.page_store.read().await;
+    let suggestions: Vec<_> = read
+        .suggest_tags(
+            query.q.as_deref().unwrap_or(""),
+            &context_tags,
+            TAG_SUGGESTION_COUNT,
+        )
+        .into_iter()
+        .map(|(tag, count)| TagSuggestion { tag, count })
+        .collect();
+    Ok(warp::reply::json(&suggestions))
+}
+
+async fn handle_tasks_get_wrapped(
+    state: Arc<State>,
+    path: FullPath,
+) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
+    let tags_path = if let Some(tags_path) = path.as_str().strip_suffix(TASKS_PATH_SUFFIX) {
+        tags_path.to_owned() + "/"
+    } else {
+        return Err(warp::reject::not_found());
+    };
+    handle_tasks_get(state, tags_path)
+        .await
+        .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
+}
+
+async fn handle_tasks_get(state: Arc<State>, tags_path: String) -> Result<Box<dyn warp::Reply>> {
+    let tags = path_str_to_tags(&tags_path);
+    let tags: Vec<_> = tags.iter().map(String::as_str).collect();
+    let read = state.page_store.read().await;
+    let results = read.find(tags.as_slice());
+    if results.matching_tags != tags {
+        let path = tags_to_path(&results.matching_tags);
+        return Ok(Box::new(warp_temporary_redirect(&format!(
+            "{}{}",
+            path.trim_end_matches('/'),
+            TASKS_PATH_SUFFIX
+        ))));
+    }
+
+    Ok(warp_reply_from_render(render::html_page(
+        &state.layout,
+        render::tasks(
+            results
+                .matching_tags
+                .iter()
+                .map(|tag| tag.to_string())
+                .collect(),
+            read.open_tasks(&tags),
+        ),
+    )))
+}
+
+async fn handle_task_post_wrapped(
+    state: Arc<State>,
+    headers: warp::http::HeaderMap,
+    form: TaskForm,
+) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
+    handle_task_post(state, headers, form)
+        .await
+        .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
+}
+
+/// Check or uncheck a task list item (from a checkbox in the page view)
+async fn handle_task_post(
+    state: Arc<State>,
+    headers: warp::http::HeaderMap,
+    form: TaskForm,
+) -> Result<Box<dyn warp::Reply>> {
+    let mut write = state.page_store.write().await;
+    let mut page = write
+        .get(form.id)
+        .await?
+        .with_task_done(form.index, &form.text, form.done)?;
+    page.record_modification(
+        state.request_author(&headers),
+        if form.done {
+            "checked a task"
+        } else {
+            "unchecked a task"
+        }
+        .into(),
+    );
+    write.put(&page).await?;
+    Ok(Box::new(warp::http::StatusCode::NO_CONTENT))
+}
+
+async fn handle_draft_post_wrapped(
+    state: Arc<State>,
+    key: String,
+    referer: Option<String>,
+    form: DraftForm,
+) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
+    handle_draft_post(state, key, referer, form)
+        .await
+        .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
+}
+
+/// Save (by the editor script), or discard (`_method=delete`) a draft
+async fn handle_draft_post(
+    state: Arc<State>,
+    key: String,
+    referer: Option<String>,
+    form: DraftForm,
+) -> Result<Box<dyn warp::Reply>> {
+    if let Some("delete") = form._method.as_deref() {
+        state.drafts.delete(&key)?;
+        // back to where the "Discard" button was
+        return Ok(Box::new(warp_temporary_redirect_to_get_method(
+            referer.as_deref().unwrap_or("/"),
+        )));
+    }
+
+    let body = form.body.ok_or_else(|| format_err!("Missing body"))?;
+    state.drafts.put(&key, &get_rid_of_windows_newlines(body))?;
+    Ok(Box::new(warp::http::StatusCode::NO_CONTENT))
+}
+
+async fn handle_recent_wrapped(
+    state: Arc<State>,
+) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
+    handle_recent(state)
+        .await
+        .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
+}
+
+async fn handle_recent(state: Arc<State>) -> Result<Box<dyn warp::Reply>> {
+    let read = state.page_store.read().await;
+    let no_tags: &[&str] = &[];
+    let mut results = read.find(no_tags);
+    read.sort_results(&mut results, index::SortOrder::Modified);
+    results.matching_pages.truncate(RECENT_CHANGES_COUNT);
+
+    Ok(warp_reply_from_render(render::html_page(
+        &state.layout,
+        render::recent(results.matching_pages),
+    )))
+}
+
+async fn handle_feed_wrapped(
+    state: Arc<State>,
+    path: FullPath,
+    headers: warp::http::HeaderMap,
+) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
+    let tags_path = if let Some(tags_path) = path.as_str().strip_suffix(FEED_PATH_SUFFIX) {
+        tags_path.to_owned() + "/"
+    } else {
+        return Err(warp::reject::not_found());
+    };
+    handle_feed(state, tags_path, headers)
+        .await
+        .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
+}
+
+async fn handle_feed(
+    state: Arc<State>,
+    tags_path: String,
+    headers: warp::http::HeaderMap,
+) -> Result<Box<dyn warp::Reply>> {
+    let tags = path_str_to_tags(&tags_path);
+    let tags: Vec<_> = tags.iter().map(String::as_str).collect();
+    let read = state.page_store.read().await;
+    let results = read.find(tags.as_slice());
+    // a feed of a partial match would silently show unrelated pages
+    if results.matching_tags.len() != tags.len() {
+        bail!("No pages matching all tags");
+    }
+
+    let mut pages = vec![];
+    for info in results.matching_pages.iter().take(FEED_ENTRY_COUNT) {
+        let page = read.get(info.id.clone()).await?;
+        let html = wikilink::resolve(&read, &page, &state.render_config)
+            .await?
+            .html;
+        pages.push((page, html));
+    }
+
+    let header_str = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let base_url = format!(
+        "{}://{}",
+        header_str("x-forwarded-proto").unwrap_or("http"),
+        header_str("host").ok_or_else(|| format_err!("Missing Host header"))?
+    );
+    let title = if tags.is_empty() {
+        "tagwiki".to_owned()
+    } else {
+        format!("tagwiki: {}", tags.join(" "))
+    };
+
+    Ok(Box::new(
+        warp::http::Response::builder()
+            .status(200)
+            .header(
+                warp::http::header::CONTENT_TYPE,
+                "application/atom+xml; charset=utf-8",
+            )
+            .body(feed::atom(&base_url, &tags_path, &title, &pages))?,
+    ))
+}
+
+async fn handle_trash_get_wrapped(
+    state: Arc<State>,
+) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
+    handle_trash_get(state)
+        .await
+        .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
+}
+
+async fn handle_trash_get(state: Arc<State>) -> Result<Box<dyn warp::Reply>> {
+    let read = state.page_store.read().await;
+    let mut trashed = read.trashed().await?;
+    trashed.sort_unstable_by_key(|trashed| std::cmp::Reverse(trashed.deletion_time));
+    let trashed = trashed
+        .into_iter()
+        .map(|trashed| {
+            let attachment_count = match state.attachments.list(&trashed.id) {
+                Ok(attachments) => attachments.len(),
+                Err(e) => {
+                    eprintln!("Error listing attachments of {}: {}", trashed.id, e);
+                    0
+                }
+            };
+            (trashed, attachment_count)
+        })
+        .collect();
+
+    Ok(warp_reply_from_render(render::html_page(
+        &state.layout,
+        render::trash(trashed),
+    )))
+}
+
+async fn handle_trash_post_wrapped(
+    state: Arc<State>,
+    form: PostForm,
+) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
+    handle_trash_post(state, form)
+        .await
+        .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
+}
+
+async fn handle_trash_post(state: Arc<State>, form: PostForm) -> Result<Box<dyn warp::Reply>> {
+    let id = form.id.ok_or_else(|| format_err!("Missing ID"))?;
+    let mut write = state.page_store.write().await;
+
+    match form._method.as_deref() {
+        Some("restore") => {
+            write.restore(id.clone()).await?;
+            Ok(Box::new(warp_temporary_redirect_to_get_method(&format!(
+                "/?id={}",
+                id
+            ))))
+        }
+        Some("delete") => {
+            write.purge(id).await?;
+            Ok(Box::new(warp_temporary_redirect_to_get_method("/_trash/")))
+        }
+        _ => bail!("Unknown method"),
+    }
+}
+
+async fn handle_attachment_get_wrapped(
+    state: Arc<State>,
+    id: String,
+    name: String,
+) -> std::result::Result<warp::http::Response<Vec<u8>>, warp::Rejection> {
+    handle_attachment_get(state, id, name)
+        .await
+        .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
+}
+
+async fn handle_attachment_get(
+    state: Arc<State>,
+    id: String,
+    name: String,
+) -> Result<warp::http::Response<Vec<u8>>> {
+    let data = state.attachments.get(&id, &name)?;
+    let mime = mime_guess::from_path(&name).first_or_octet_stream();
+
+    Ok(warp::http::Response::builder()
+        .status(200)
+        .header(warp::http::header::CONTENT_TYPE, mime.as_ref())
+        .header(warp::http::header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        // don't let uploaded html & svg files run scripts
+        .header(warp::http::header::CONTENT_SECURITY_POLICY, "sandbox")
+        .body(data)
+        .expect("correct response"))
+}
+
+async fn handle_attachment_upload_wrapped(
+    state: Arc<State>,
+    id: String,
+    accept: Option<String>,
+    form: warp::multipart::FormData,
+) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
+    handle_attachment_upload(state, id, accept, form)
+        .await
+        .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
+}
+
+/// Store uploaded files
+///
+/// JS code asks for `text/plain` to get markdown links to insert into the editor,
+/// plain html forms get redirected back to the page.
+async fn handle_attachment_upload(
+    state: Arc<State>,
+    id: String,
+    accept: Option<String>,
+    form: warp::multipart::FormData,
+) -> Result<Box<dyn warp::Reply>> {
+    // files can be attached to a new page, before it is saved
+    if !page::Parsed::is_valid_new_id(&id) && !state.page_store.read().await.contains(&id) {
+        bail!("Invalid page ID: {}", id);
+    }
+    let parts: Vec<warp::multipart::Part> = form.try_collect().await?;
+
+    let mut links = vec![];
+    for mut part in parts {
+        if part.name() != "file" {
+            continue;
+        }
+        let file_name = match part.filename() {
+            Some(file_name) if !file_name.is_empty() => file_name.to_owned(),
+            _ => continue,
+        };
+        let mut data = vec![];
+        while let Some(buf) = part.data().await {
+            data.extend_from_slice(buf?.bytes());
+        }
+        let name = state.attachments.put(&id, &file_name, &data)?;
+        links.push(attachment::markdown_link(&id, &name));
+    }
+
+    if accept.as_deref() == Some("text/plain") {
+        Ok(Box::new(links.join("\n")))
+    } else {
+        Ok(Box::new(warp_temporary_redirect_to_get_method(&format!(
+            "/?id={}",
+            id
+        ))))
+    }
+}
+
 // I wish this could be generic
 async fn handle_get_wrapped(
     state: Arc<State>,
     path: FullPath,
     query: GetParams,
+    headers: warp::http::HeaderMap,
 ) -> std::result::Result<Box<dyn warp::Reply>, warp::Rejection> {
-    handle_get(state, path, query)
+    handle_get(state, path, query, headers)
         .await
         .map_err(|e| warp::reject::custom(RejectAnyhow(e)))
 }
@@ -249,6 +907,7 @@ async fn handle_get(
     state: Arc<State>,
     path: FullPath,
     query: GetParams,
+    headers: warp::http::HeaderMap,
 ) -> Result<Box<dyn warp::Reply>> {
     // rediect anything that does not end with `/`
     // This way relative links always work as expected.
@@ -270,20 +929,93 @@ async fn handle_get(
         ))));
     }
     let tags = path_to_tags(&path);
+    let tags: Vec<_> = tags.iter().map(String::as_str).collect();
     let page_state = render::PageState {
         original_page_id: query.id.clone(),
         page: None,
+        rendered: None,
         edit: query.edit.is_some(),
         path: path.as_str().to_string(),
         subtags: vec![],
+        attachments: vec![],
+        new_page_template: None,
+        templates: vec![],
+        author: state.request_author(&headers),
+        drafts: vec![],
+        new_page_draft: None,
+        new_page_title: query.title.clone(),
+        dropped_tags: query
+            .dropped
+            .as_deref()
+            .unwrap_or("")
+            .split_whitespace()
+            .map(str::to_owned)
+            .collect(),
     };
 
     let read = state.page_store.read().await;
 
-    let results = read.find(tags.as_slice());
+    // before any redirects, as there might be no journal pages yet
+    if query.today.is_some() {
+        if let Some(info) = read.find_created_on(&tags, util::today()) {
+            return Ok(Box::new(warp_temporary_redirect_to_get_method(&format!(
+                "?id={}",
+                info.id
+            ))));
+        }
+        return Ok(warp_reply_from_render(render::html_page(
+            &state.layout,
+            render::page(render::PageState {
+                edit: true,
+                new_page_template: Some(state.daily_note_template.clone()),
+                ..page_state
+            }),
+        )));
+    }
+
+    let mut results = read.find(tags.as_slice());
     if results.matching_tags != tags {
-        return Ok(Box::new(warp_temporary_redirect(
-            &("/".to_string() + &results.matching_tags.join("/")),
+        let dropped: Vec<_> = tags
+            .iter()
+            .filter(|tag| !results.matching_tags.iter().any(|m| m.as_str() == **tag))
+            .copied()
+            .collect();
+        let path = tags_to_path(&results.matching_tags);
+        return Ok(Box::new(warp_temporary_redirect(&format!(
+            "{}/?dropped={}",
+            path.trim_end_matches('/'),
+            percent_encoding::utf8_percent_encode(
+                &dropped.join(" "),
+                percent_encoding::NON_ALPHANUMERIC
+            )
+        ))));
+    }
+
+    if let Some(month) = query.calendar.as_deref() {
+        let month = chrono::NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d")
+            .unwrap_or_else(|_| util::today().with_day(1).expect("valid day"));
+        let mut pages_by_day = std::collections::BTreeMap::<_, Vec<_>>::new();
+        for info in &results.matching_pages {
+            let date = util::local_date(info.headers.creation_time);
+            if date.year() == month.year() && date.month() == month.month() {
+                pages_by_day
+                    .entry(date.day())
+                    .or_default()
+                    .push(info.clone());
+            }
+        }
+        for pages in pages_by_day.values_mut() {
+            pages.reverse();
+        }
+        return Ok(warp_reply_from_render(render::html_page(
+            &state.layout,
+            render::calendar(
+                page_state,
+                render::CalendarMonth {
+                    month,
+                    pages_by_day,
+                },
+            ),
         )));
     }
 
@@ -301,12 +1033,17 @@ async fn handle_get(
                 compact_results.tags,
             )
         } else {
+            let sort = query.sort.unwrap_or_default();
+            read.sort_results(&mut results, sort);
+            let listing = render::Listing::new(sort, query.page, results.matching_pages.len());
+            let posts = results
+                .matching_pages
+                .into_iter()
+                .skip(listing.skip())
+                .take(render::LISTING_PAGE_SIZE);
             return Ok(warp_reply_from_render(render::html_page(
-                render::post_list(
-                    page_state,
-                    compact_results.tags,
-                    results.matching_pages.into_iter(),
-                ),
+                &state.layout,
+                render::post_list(page_state, compact_results.tags, posts, listing),
             )));
         }
     };
@@ -316,38 +1053,372 @@ async fn handle_get(
     } else {
         None
     };
+    let (new_page_template, templates) = if page.is_none() && page_state.edit {
+        let template_id = match query.template.as_deref() {
+            Some("") => None,
+            Some(id) => Some(id.to_owned()),
+            None => read.template_for(&tags).map(|info| info.id.clone()),
+        };
+        let new_page_template = if let Some(template_id) = template_id {
+            Some(template::from_page_body(
+                &read.get(template_id).await?.source_body,
+            ))
+        } else {
+            None
+        };
+        let new_page_template = if let Some(title) = query.title.as_deref() {
+            Some(template::with_title(
+                new_page_template
+                    .as_deref()
+                    .unwrap_or(template::DEFAULT_NEW_PAGE),
+                title,
+            ))
+        } else {
+            new_page_template
+        };
+        (new_page_template, read.templates())
+    } else {
+        (None, vec![])
+    };
+    let attachments = if let Some(page) = page.as_ref() {
+        state.attachments.list(page.id())?
+    } else {
+        vec![]
+    };
 
-    Ok(warp_reply_from_render(render::html_page(render::page(
-        render::PageState {
+    let mut rendered = None;
+    let (page, drafts, new_page_draft) = if !page_state.edit {
+        if let Some(page) = page.as_ref() {
+            rendered = Some(wikilink::resolve(&read, page, &state.render_config).await?);
+        }
+        (page, vec![], None)
+    } else if let Some(key) = query.draft.as_deref() {
+        let draft = state
+            .drafts
+            .get(key)?
+            .ok_or_else(|| format_err!("Draft not found"))?;
+        if let Some(page) = page {
+            (Some(page.with_new_source_body(&draft.body)), vec![], None)
+        } else {
+            (None, vec![], Some(draft))
+        }
+    } else if let Some(page) = page {
+        let drafts = state.drafts.get(page.id())?.into_iter().collect();
+        (Some(page), drafts, None)
+    } else {
+        // drafts of pages that were never saved
+        let drafts = state
+            .drafts
+            .list()?
+            .into_iter()
+            .filter(|draft| !read.contains(&draft.key))
+            .collect();
+        (None, drafts, None)
+    };
+
+    Ok(warp_reply_from_render(render::html_page(
+        &state.layout,
+        render::page(render::PageState {
             page,
+            rendered,
             subtags,
+            attachments,
+            new_page_template,
+            templates,
+            drafts,
+            new_page_draft,
             ..page_state
-        },
-    ))))
+        }),
+    )))
+}
+
+fn fs_store(opts: &cli::Opts) -> Result<page::store::FsStore> {
+    Ok(page::store::FsStore::new(opts.path.clone())?
+        .with_rename_on_title_change(opts.rename_on_title_change)
+        .with_trash_retention(opts.trash_retention_days.map(chrono::Duration::days)))
+}
+
+fn daily_note_template(opts: &cli::Opts) -> Result<String> {
+    Ok(if let Some(path) = opts.daily_note_template.as_ref() {
+        std::fs::read_to_string(path)
+            .map_err(|e| format_err!("Can't read {}: {}", path.display(), e))?
+    } else {
+        template::DEFAULT_DAILY_NOTE.to_owned()
+    })
+}
+
+/// Port of the server running on the wiki, if any
+fn running_server_port(opts: &cli::Opts) -> Option<u16> {
+    let port = std::fs::read_to_string(opts.path.join(SERVER_PORT_FILE))
+        .ok()?
+        .trim()
+        .parse::<u16>()
+        .ok()?;
+    // the file is left behind when the server is stopped
+    std::net::TcpStream::connect(("127.0.0.1", port))
+        .ok()
+        .map(|_| port)
+}
+
+/// Fail if the server is running on the wiki
+///
+/// It keeps pages in memory, so would overwrite the ones changed behind its back.
+fn check_server_not_running(opts: &cli::Opts) -> Result<()> {
+    if let Some(port) = running_server_port(opts) {
+        bail!(
+            "The wiki server is running (on port {}), stop it first (or remove {}, if it's not)",
+            port,
+            opts.path.join(SERVER_PORT_FILE).display()
+        );
+    }
+    Ok(())
+}
+
+/// Append `text` to a page through the server running on `port` (see `handle_append`)
+async fn append_through_server(
+    port: u16,
+    id: Option<String>,
+    tags: &[&str],
+    text: &str,
+) -> Result<page::Id> {
+    let encode = |s: &str| {
+        percent_encoding::utf8_percent_encode(s, percent_encoding::NON_ALPHANUMERIC).to_string()
+    };
+    let tags_path: String = tags
+        .iter()
+        .map(|tag| format!("/{}", util::encode_path_segment(tag)))
+        .collect();
+    let mut form = format!("text={}", encode(text));
+    if let Some(id) = id {
+        form += &format!("&id={}", encode(&id));
+    }
+    let request = hyper::Request::post(format!("{}{}", tags_path, APPEND_PATH_SUFFIX))
+        .header(warp::http::header::HOST, format!("127.0.0.1:{}", port))
+        .header(
+            warp::http::header::CONTENT_TYPE,
+            "application/x-www-form-urlencoded",
+        )
+        .header(warp::http::header::ACCEPT, "text/plain")
+        .body(hyper::Body::from(form))?;
+    let stream = std::net::TcpStream::connect(("127.0.0.1", port))?;
+    stream.set_nonblocking(true)?;
+    let stream = tokio::net::TcpStream::from_std(stream)?;
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await?;
+    tokio::spawn(connection);
+    let response = sender.send_request(request).await?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    let body = String::from_utf8_lossy(&body).trim().to_owned();
+    if !status.is_success() {
+        bail!("The wiki server failed to append ({}): {}", status, body);
+    }
+    Ok(body)
+}
+
+/// `tagwiki today`: print the path of today's journal page, creating it if needed
+async fn today(opts: &cli::Opts) -> Result<()> {
+    check_server_not_running(opts)?;
+    let mut index = index::Index::new(fs_store(opts)?).await?;
+    let tags: Vec<_> = opts.journal_tags.iter().map(String::as_str).collect();
+
+    let id = if let Some(info) = index.find_created_on(&tags, util::today()) {
+        info.id.clone()
+    } else {
+        let mut page = page::Parsed::new(&template::expand(
+            &daily_note_template(opts)?,
+            &template::Context {
+                time: util::now(),
+                tags: &tags,
+                user: None,
+            },
+        ));
+        page.record_modification(None, "created".into());
+        index.put(&page).await?;
+        page.id().to_owned()
+    };
+
+    let path = index
+        .store()
+        .page_file_path(&id)
+        .ok_or_else(|| format_err!("Page file not found"))?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+/// `tagwiki append`: append a timestamped line to a page
+///
+/// Through the server, if it's running, as it would overwrite pages changed behind its back.
+async fn append(
+    opts: &cli::Opts,
+    id: Option<String>,
+    tags: &[String],
+    text: &[String],
+) -> Result<()> {
+    let tags: Vec<_> = tags.iter().map(String::as_str).collect();
+    let text = if text.is_empty() {
+        let mut text = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut text)?;
+        text
+    } else {
+        text.join(" ")
+    };
+
+    let id = if let Some(port) = running_server_port(opts) {
+        append_through_server(port, id, &tags, &text).await?
+    } else {
+        let mut index = index::Index::new(fs_store(opts)?).await?;
+        append_to_page(&mut index, id, &tags, &text, None).await?
+    };
+    println!("{}", id);
+    Ok(())
+}
+
+/// Remove attachments of new pages that were never saved
+///
+/// Only ones older than `ORPHANED_ATTACHMENTS_AGE`, as the page might still be being edited.
+async fn remove_orphaned_attachments(state: &State) -> Result<()> {
+    let read = state.page_store.read().await;
+    let trashed: Vec<_> = read
+        .trashed()
+        .await?
+        .into_iter()
+        .map(|trashed| trashed.id)
+        .collect();
+    let now = std::time::SystemTime::now();
+    for (id, modification_time) in state.attachments.page_ids()? {
+        let is_old = now
+            .duration_since(modification_time)
+            .map(|age| ORPHANED_ATTACHMENTS_AGE < age)
+            .unwrap_or(false);
+        if is_old && !read.contains(&id) && !trashed.contains(&id) {
+            info!("Removing attachments of a page that doesn't exist: {}", id);
+            state.attachments.delete_all(&id)?;
+        }
+    }
+    Ok(())
 }
 
 async fn start(opts: &cli::Opts) -> Result<()> {
     let state = Arc::new(State {
         page_store: Arc::new(tokio::sync::RwLock::new(
-            index::Index::new(Box::new(page::store::FsStore::new(opts.path.clone())?)
-                as Box<dyn page::store::StoreMut + Send + Sync>)
+            index::Index::new(
+                Box::new(fs_store(opts)?) as Box<dyn page::store::StoreMut + Send + Sync>
+            )
             .await?,
         )),
+        attachments: attachment::AttachmentStore::new(&opts.path),
+        drafts: draft::DraftStore::new(&opts.path),
+        author_header: opts.author_header.clone(),
+        journal_tags: opts.journal_tags.clone(),
+        daily_note_template: daily_note_template(opts)?,
+        render_config: render_config(opts)?,
+        layout: layout(opts)?,
     });
+    if let Err(e) = remove_orphaned_attachments(&state).await {
+        eprintln!("Error removing orphaned attachments: {}", e);
+    }
     let handler = warp::any()
-        .and(warp::path!("_style.css").and_then(handle_style_css))
-        .or(warp::path!("_script.js").and_then(handle_script_js))
+        .and(with_state(state.clone()))
+        .and(warp::path!("_assets" / String / String))
+        .and_then(handle_asset)
+        // URLs of the assets before they were versioned
+        .or(with_state(state.clone())
+            .and(warp::path!("_style.css"))
+            .map(|state: Arc<State>| {
+                warp_temporary_redirect(&state.layout.assets.url("style.css"))
+            }))
+        .or(with_state(state.clone())
+            .and(warp::path!("_script.js"))
+            .map(|state: Arc<State>| {
+                warp_temporary_redirect(&state.layout.assets.url("script.js"))
+            }))
+        .or(with_state(state.clone())
+            .and(warp::path!("_preview"))
+            .and(warp::post())
+            .and(warp::filters::body::form())
+            .and_then(handle_preview_wrapped))
         .or(warp::path!("_query")
             .and(warp::query::<GetParams>())
             .and_then(handle_query))
+        .or(with_state(state.clone())
+            .and(warp::path!("_attachments" / String / String))
+            .and(warp::get())
+            .and_then(handle_attachment_get_wrapped))
+        .or(with_state(state.clone())
+            .and(warp::path!("_attachments" / String))
+            .and(warp::post())
+            .and(warp::header::optional::<String>("accept"))
+            .and(warp::multipart::form().max_length(MAX_ATTACHMENT_UPLOAD_SIZE))
+            .and_then(handle_attachment_upload_wrapped))
+        .or(with_state(state.clone())
+            .and(warp::path::full())
+            .and(warp::get())
+            .and(warp::header::headers_cloned())
+            .and_then(handle_feed_wrapped))
+        .or(with_state(state.clone())
+            .and(warp::path::full())
+            .and(warp::get())
+            .and_then(handle_tasks_get_wrapped))
+        .or(with_state(state.clone())
+            .and(warp::path::full().and_then(|path: FullPath| async move {
+                path.as_str()
+                    .strip_suffix(APPEND_PATH_SUFFIX)
+                    .map(|tags_path| tags_path.to_owned() + "/")
+                    .ok_or_else(warp::reject::not_found)
+            }))
+            .and(warp::post())
+            .and(warp::header::headers_cloned())
+            .and(warp::filters::body::form())
+            .and_then(handle_append_wrapped))
+        .or(with_state(state.clone())
+            .and(warp::path!("_drafts" / String))
+            .and(warp::post())
+            .and(warp::header::optional::<String>("referer"))
+            .and(warp::filters::body::form())
+            .and_then(handle_draft_post_wrapped))
+        .or(with_state(state.clone())
+            .and(warp::path!("_tasks"))
+            .and(warp::post())
+            .and(warp::header::headers_cloned())
+            .and(warp::filters::body::form())
+            .and_then(handle_task_post_wrapped))
+        .or(with_state(state.clone())
+            .and(warp::path!("_tags"))
+            .and(warp::get())
+            .and(warp::query::<TagSuggestionParams>())
+            .and_then(handle_tag_suggestions))
+        .or(with_state(state.clone())
+            .and(warp::path!("_today"))
+            .and(warp::get())
+            .and_then(handle_today))
+        .or(with_state(state.clone())
+            .and(warp::path!("_link"))
+            .and(warp::get())
+            .and(warp::query::<LinkParams>())
+            .and_then(handle_link_wrapped))
+        .or(with_state(state.clone())
+            .and(warp::path!("_recent"))
+            .and(warp::get())
+            .and_then(handle_recent_wrapped))
+        .or(with_state(state.clone())
+            .and(warp::path!("_trash"))
+            .and(warp::get())
+            .and_then(handle_trash_get_wrapped))
+        .or(with_state(state.clone())
+            .and(warp::path!("_trash"))
+            .and(warp::post())
+            .and(warp::filters::body::form())
+            .and_then(handle_trash_post_wrapped))
         .or(with_state(state.clone())
             .and(warp::path::full())
             .and(warp::query::<GetParams>())
             .and(warp::get())
+            .and(warp::header::headers_cloned())
             .and_then(handle_get_wrapped))
         .or(with_state(state.clone())
             .and(warp::path::full())
             .and(warp::post())
+            .and(warp::header::headers_cloned())
             .and(warp::filters::body::form())
             .and_then(handle_post_wrapped))
         .or(with_state(state.clone())
@@ -358,21 +1429,72 @@ async fn start(opts: &cli::Opts) -> Result<()> {
         .or(with_state(state)
             .and(warp::path::full())
             .and(warp::put())
+            .and(warp::header::headers_cloned())
             .and(warp::filters::body::form())
             .and_then(handle_put_wrapped));
     info!("Listening on port {}", opts.port);
+    std::fs::write(opts.path.join(SERVER_PORT_FILE), opts.port.to_string())?;
     let _serve = warp::serve(handler).run(([127, 0, 0, 1], opts.port)).await;
 
     Ok(())
 }
 
+/// Assets (with the highlighting theme and custom ones) and page shell of the wiki
+fn layout(opts: &cli::Opts) -> Result<render::Layout> {
+    let mut assets = assets::Assets::builtin();
+    let mut highlight_css = highlight::theme_css(&opts.highlight_theme)?;
+    if !opts.highlight_dark_theme.is_empty() {
+        highlight_css += &highlight::dark_theme_css(&opts.highlight_dark_theme)?;
+    }
+    assets.register("highlight.css", "text/css", highlight_css);
+    assets.load_custom(&opts.path)?;
+    let layout = render::Layout::new(assets);
+    let shell_path = opts.path.join(SHELL_TEMPLATE_FILE);
+    if !shell_path.exists() {
+        return Ok(layout);
+    }
+    layout.with_shell_template(
+        &std::fs::read_to_string(&shell_path)
+            .map_err(|e| format_err!("Can't read {}: {}", shell_path.display(), e))?,
+    )
+}
+
+fn render_config(opts: &cli::Opts) -> Result<page::RenderConfig> {
+    let non_empty = |command: &String| Some(command.clone()).filter(|command| !command.is_empty());
+    Ok(page::RenderConfig {
+        markdown_extensions: opts
+            .markdown_extensions
+            .iter()
+            .filter(|name| !name.is_empty())
+            .map(|name| name.parse())
+            .collect::<Result<_>>()?,
+        sanitize: if opts.no_sanitize_html {
+            None
+        } else {
+            Some(sanitize::Config {
+                extra_tags: opts.allow_html_tags.clone(),
+                extra_attributes: opts.allow_html_attributes.clone(),
+            })
+        },
+        diagram_commands: diagram::Commands {
+            dot: non_empty(&opts.dot_command),
+            mermaid: non_empty(&opts.mermaid_command),
+        },
+    })
+}
+
 fn main() -> Result<()> {
     env_logger::init();
     let opts = cli::Opts::from_args();
 
-    tokio::runtime::Runtime::new()
-        .unwrap()
-        .block_on(start(&opts))?;
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    match opts.command.as_ref() {
+        Some(cli::Command::Today) => runtime.block_on(today(&opts))?,
+        Some(cli::Command::Append { id, tags, text }) => {
+            runtime.block_on(append(&opts, id.clone(), tags, text))?
+        }
+        None => runtime.block_on(start(&opts))?,
+    }
 
     Ok(())
 }
@@ -405,3 +1527,191 @@ async fn handle_rejection(
 
     Ok(warp::reply::with_status(message, code))
 }*/
+
+#[cfg(test)]
+async fn test_state(root_path: &std::path::Path) -> Result<Arc<State>> {
+    Ok(Arc::new(State {
+        page_store: Arc::new(tokio::sync::RwLock::new(
+            index::Index::new(Box::new(page::store::InMemoryStore::new())
+                as Box<dyn page::store::StoreMut + Send + Sync>)
+            .await?,
+        )),
+        attachments: attachment::AttachmentStore::new(root_path),
+        drafts: draft::DraftStore::new(root_path),
+        author_header: None,
+        journal_tags: vec!["journal".into()],
+        daily_note_template: template::DEFAULT_DAILY_NOTE.to_owned(),
+        render_config: page::RenderConfig::default(),
+        layout: render::Layout::new(assets::Assets::builtin()),
+    }))
+}
+
+#[cfg(test)]
+fn get_filter(
+    state: Arc<State>,
+) -> impl Filter<Extract = (Box<dyn warp::Reply>,), Error = warp::Rejection> + Clone {
+    with_state(state)
+        .and(warp::path::full())
+        .and(warp::query::<GetParams>())
+        .and(warp::get())
+        .and(warp::header::headers_cloned())
+        .and_then(handle_get_wrapped)
+}
+
+#[tokio::test]
+async fn today_in_empty_wiki_test() -> Result<()> {
+    let root_path = std::env::temp_dir().join(format!("tagwiki-test-{}", util::random_string(8)));
+    let state = test_state(&root_path).await?;
+
+    let reply = warp::test::request()
+        .path("/_today")
+        .reply(
+            &warp::path!("_today")
+                .and(with_state(state.clone()))
+                .and_then(handle_today),
+        )
+        .await;
+    assert_eq!(
+        reply.headers()[warp::http::header::LOCATION],
+        "/journal/?today=true"
+    );
+
+    // there are no `#journal` pages to match yet, but the editor of the first one opens
+    let reply = warp::test::request()
+        .path("/journal/?today=true")
+        .reply(&get_filter(state))
+        .await;
+    assert_eq!(reply.status(), 200);
+    assert!(String::from_utf8_lossy(reply.body()).contains("#journal"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn draft_discarded_on_save_test() -> Result<()> {
+    let root_path = std::env::temp_dir().join(format!("tagwiki-test-{}", util::random_string(8)));
+    let state = test_state(&root_path).await?;
+    let put_filter = with_state(state.clone())
+        .and(warp::path::full())
+        .and(warp::put())
+        .and(warp::header::headers_cloned())
+        .and(warp::filters::body::form())
+        .and_then(handle_put_wrapped);
+    let post_filter = with_state(state.clone())
+        .and(warp::path::full())
+        .and(warp::post())
+        .and(warp::header::headers_cloned())
+        .and(warp::filters::body::form())
+        .and_then(handle_post_wrapped);
+
+    // a new page, saved with the ID its draft was kept under
+    state.drafts.put("abc", "# New #draft")?;
+    let reply = warp::test::request()
+        .method("PUT")
+        .path("/")
+        .body("new_id=abc&body=%23+New+%23draft")
+        .reply(&put_filter)
+        .await;
+    assert_eq!(reply.headers()[warp::http::header::LOCATION], "?id=abc");
+    assert!(state.drafts.get("abc")?.is_none());
+
+    // the ID is not replaced with another one, if it's taken or invalid
+    for new_id in &["abc", "..%2Fabc"] {
+        let reply = warp::test::request()
+            .method("PUT")
+            .path("/")
+            .body(format!("new_id={}&body=%23+Other", new_id))
+            .reply(&put_filter)
+            .await;
+        assert!(!reply.status().is_redirection());
+    }
+    assert_eq!(
+        state.page_store.read().await.find(&[]).matching_pages.len(),
+        1
+    );
+
+    // an existing page
+    state.drafts.put("abc", "# Changed #draft")?;
+    let reply = warp::test::request()
+        .method("POST")
+        .path("/")
+        .body("id=abc&body=%23+Changed+%23draft")
+        .reply(&post_filter)
+        .await;
+    assert_eq!(reply.headers()[warp::http::header::LOCATION], "?id=abc");
+    assert!(state.drafts.get("abc")?.is_none());
+    assert_eq!(
+        state
+            .page_store
+            .read()
+            .await
+            .get("abc".into())
+            .await?
+            .source_body,
+        "# Changed #draft"
+    );
+
+    std::fs::remove_dir_all(root_path)?;
+    Ok(())
+}
+
+#[test]
+fn check_server_not_running_test() -> Result<()> {
+    let root_path = std::env::temp_dir().join(format!("tagwiki-test-{}", util::random_string(8)));
+    std::fs::create_dir_all(&root_path)?;
+    let opts = cli::Opts::from_iter(&["tagwiki", &root_path.to_string_lossy()]);
+    check_server_not_running(&opts)?;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    std::fs::write(root_path.join(SERVER_PORT_FILE), port.to_string())?;
+    assert!(check_server_not_running(&opts).is_err());
+    drop(listener);
+    check_server_not_running(&opts)?;
+
+    std::fs::remove_dir_all(root_path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn append_through_server_test() -> Result<()> {
+    let root_path = std::env::temp_dir().join(format!("tagwiki-test-{}", util::random_string(8)));
+    std::fs::create_dir_all(&root_path)?;
+    let state = test_state(&root_path).await?;
+    let append_filter = with_state(state.clone())
+        .and(warp::path::full().and_then(|path: FullPath| async move {
+            path.as_str()
+                .strip_suffix(APPEND_PATH_SUFFIX)
+                .map(|tags_path| tags_path.to_owned() + "/")
+                .ok_or_else(warp::reject::not_found)
+        }))
+        .and(warp::post())
+        .and(warp::header::headers_cloned())
+        .and(warp::filters::body::form())
+        .and_then(handle_append_wrapped);
+    let (addr, server) = warp::serve(append_filter).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    std::fs::write(root_path.join(SERVER_PORT_FILE), addr.port().to_string())?;
+
+    state
+        .page_store
+        .write()
+        .await
+        .put(&page::Parsed::new("# Inbox #inbox\n"))
+        .await?;
+    let opts = cli::Opts::from_iter(&["tagwiki", &root_path.to_string_lossy()]);
+    append(
+        &opts,
+        None,
+        &["inbox".into()],
+        &["buy".into(), "milk & eggs".into()],
+    )
+    .await?;
+    let read = state.page_store.read().await;
+    let found = read.find(&["inbox"]).matching_pages;
+    assert_eq!(found.len(), 1);
+    let page = read.get(found[0].id.clone()).await?;
+    assert!(page.source_body.contains("buy milk & eggs"));
+
+    std::fs::remove_dir_all(root_path)?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+//! `$...$` and `$$...$$` math
+//!
+//! Markdown would mangle TeX (`\\`, `*`, `_` are markdown syntax too), so math is
+//! marked before parsing markdown, with all the punctuation of the TeX source
+//! turned into character references. Rendering itself is done in the browser,
+//! by the bundled KaTeX.
+
+use lazy_static::lazy_static;
+use std::ops::Range;
+
+/// Escape TeX, so markdown leaves it alone, and it comes out the same
+fn escape_tex(tex: &str) -> String {
+    tex.chars()
+        .map(|ch| {
+            if ch.is_ascii_punctuation() || ch == '\n' {
+                format!("&#{};", ch as u32)
+            } else {
+                ch.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Inline math can't start or end with a space, and can't be followed by a digit,
+/// so prices (`$5 and $10`) are not mistaken for it.
+fn is_inline_math(body: &str, range: &Range<usize>, tex: &str) -> bool {
+    !tex.starts_with(char::is_whitespace)
+        && !tex.ends_with(|ch: char| ch.is_whitespace() || ch == '\\')
+        && !body[range.end..].starts_with(|ch: char| ch.is_ascii_digit())
+}
+
+/// Byte ranges of math in a markdown `body`, with (inline) HTML elements holding its TeX
+/// source to replace them with
+///
+/// `code_ranges` are byte ranges of code (blocks and spans), left unchanged.
+/// The HTML is always a single line, longer than the math it replaces.
+pub fn math_html(body: &str, code_ranges: &[Range<usize>]) -> Vec<(Range<usize>, String)> {
+    lazy_static! {
+        static ref RE: regex::Regex =
+            regex::Regex::new(r"(?s)\$\$(.+?)\$\$|\$([^$\n]+)\$").expect("correct regex");
+    }
+
+    let mut found = vec![];
+    let mut pos = 0;
+    while let Some(cap) = RE.captures(&body[pos..]) {
+        let whole = cap.get(0).expect("be there");
+        let range = (pos + whole.start())..(pos + whole.end());
+        let escaped = body[..range.start].ends_with('\\');
+        let in_code = code_ranges
+            .iter()
+            .any(|code| code.start < range.end && range.start < code.end);
+        let html = if escaped || in_code {
+            None
+        } else if let Some(tex) = cap.get(1) {
+            Some(format!(
+                r#"<span class="math math-display">{}</span>"#,
+                escape_tex(tex.as_str().trim())
+            ))
+        } else if is_inline_math(body, &range, &cap[2]) {
+            Some(format!(
+                r#"<span class="math math-inline">{}</span>"#,
+                escape_tex(&cap[2])
+            ))
+        } else {
+            None
+        };
+
+        if let Some(html) = html {
+            pos = range.end;
+            found.push((range, html));
+        } else {
+            // the closing `$` might still open some math
+            pos = range.start + 1;
+        }
+    }
+    found
+}
+
+#[test]
+fn math_html_test() {
+    let body = "Costs $5 and $10, \\$x$ is not math, `$a$` either, $5 and $y$ is.\n\n\
+                Inline $a_1 * b_1$ and\n\n$$\n\\frac{1}{2} \\\\\n\n x\n$$\n";
+    let code_start = body.find('`').unwrap();
+    let code = code_start..code_start + 5;
+    let mut marked = body.to_owned();
+    for (range, html) in math_html(body, std::slice::from_ref(&code))
+        .into_iter()
+        .rev()
+    {
+        marked.replace_range(range, &html);
+    }
+    assert_eq!(
+        marked,
+        "Costs $5 and $10, \\$x$ is not math, `$a$` either, \
+         $5 and <span class=\"math math-inline\">y</span> is.\n\n\
+         Inline <span class=\"math math-inline\">a&#95;1 &#42; b&#95;1</span> and\n\n\
+         <span class=\"math math-display\">&#92;frac&#123;1&#125;&#123;2&#125; &#92;&#92;&#10;&#10; x</span>\n"
+    );
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, pulldown_cmark::Parser::new(&marked));
+    assert!(html.contains("<span class=\"math math-inline\">a_1 * b_1</span>"));
+    assert!(
+        html.contains("<p><span class=\"math math-display\">\\frac{1}{2} \\\\\n\n x</span></p>")
+    );
+}
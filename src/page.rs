@@ -2,11 +2,15 @@ pub mod store;
 
 use crate::util;
 #[allow(unused)]
-use anyhow::Result;
+use anyhow::{bail, format_err, Result};
 use chrono::prelude::*;
 use lazy_static::lazy_static;
-use std::collections::HashSet;
-pub use store::{InMemoryStore, Store, StoreMut};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Range,
+};
+pub use store::{Store, StoreMut};
 
 use digest::Digest;
 
@@ -18,21 +22,404 @@ pub type IdRef<'a> = &'a str;
 const TAGWIKI_PAGE_ID_KEY: &str = "tagwiki-page-id";
 const TAGWIKI_CREATION_TIME_KEY: &str = "tagwiki-creation-time";
 const TAGWIKI_MODIFICATION_TIME_KEY: &str = "tagwiki-modification-time";
+const TAGWIKI_MODIFIED_BY_KEY: &str = "tagwiki-modified-by";
+const TAGWIKI_CHANGE_SUMMARY_KEY: &str = "tagwiki-change-summary";
+
+/// Optional markdown syntax, beyond CommonMark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkdownExtension {
+    Tables,
+    Tasklists,
+    Footnotes,
+    Strikethrough,
+    // `$...$` and `$$...$$`, see `crate::math`
+    Math,
+}
+
+impl MarkdownExtension {
+    pub const ALL: [MarkdownExtension; 5] = [
+        MarkdownExtension::Tables,
+        MarkdownExtension::Tasklists,
+        MarkdownExtension::Footnotes,
+        MarkdownExtension::Strikethrough,
+        MarkdownExtension::Math,
+    ];
+
+    fn option(self) -> pulldown_cmark::Options {
+        use pulldown_cmark::Options;
+        match self {
+            MarkdownExtension::Tables => Options::ENABLE_TABLES,
+            MarkdownExtension::Tasklists => Options::ENABLE_TASKLISTS,
+            MarkdownExtension::Footnotes => Options::ENABLE_FOOTNOTES,
+            MarkdownExtension::Strikethrough => Options::ENABLE_STRIKETHROUGH,
+            MarkdownExtension::Math => Options::empty(),
+        }
+    }
+}
+
+impl std::str::FromStr for MarkdownExtension {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "tables" => MarkdownExtension::Tables,
+            "tasklists" => MarkdownExtension::Tasklists,
+            "footnotes" => MarkdownExtension::Footnotes,
+            "strikethrough" => MarkdownExtension::Strikethrough,
+            "math" => MarkdownExtension::Math,
+            _ => return Err(format_err!("Unknown markdown extension: {}", s)),
+        })
+    }
+}
+
+/// How pages are rendered to HTML; a setting of the whole wiki
+#[derive(Debug, Clone)]
+pub struct RenderConfig {
+    pub markdown_extensions: Vec<MarkdownExtension>,
+    // `None` if sanitization is disabled
+    pub sanitize: Option<crate::sanitize::Config>,
+    pub diagram_commands: crate::diagram::Commands,
+}
+
+impl Default for RenderConfig {
+    /// All markdown extensions, sanitization, and no diagrams
+    fn default() -> Self {
+        Self {
+            markdown_extensions: MarkdownExtension::ALL.to_vec(),
+            sanitize: Some(crate::sanitize::Config::default()),
+            diagram_commands: crate::diagram::Commands::default(),
+        }
+    }
+}
+
+impl RenderConfig {
+    fn is_enabled(&self, extension: MarkdownExtension) -> bool {
+        self.markdown_extensions.contains(&extension)
+    }
+
+    fn new_markdown_parser<'a>(&self, body: &'a str) -> pulldown_cmark::Parser<'a> {
+        let options = self
+            .markdown_extensions
+            .iter()
+            .fold(pulldown_cmark::Options::empty(), |options, extension| {
+                options | extension.option()
+            });
+        pulldown_cmark::Parser::new_ext(body, options)
+    }
+
+    /// Markdown `body` with the syntax not supported by the markdown parser marked up as HTML
+    fn preprocess_markdown(&self, body: &str) -> Preprocessed {
+        let replaced = if self.is_enabled(MarkdownExtension::Math) {
+            crate::math::math_html(body, &self.code_ranges(body))
+        } else {
+            vec![]
+        };
+        let mut markdown = String::new();
+        let mut shifts = vec![];
+        let mut last = 0;
+        for (range, html) in replaced {
+            markdown.push_str(&body[last..range.start]);
+            markdown.push_str(&html);
+            shifts.push((markdown.len(), markdown.len() - range.end));
+            last = range.end;
+        }
+        markdown.push_str(&body[last..]);
+        Preprocessed { markdown, shifts }
+    }
+
+    /// Byte ranges of code blocks and spans in a markdown `body`
+    fn code_ranges(&self, body: &str) -> Vec<Range<usize>> {
+        self.new_markdown_parser(body)
+            .into_offset_iter()
+            .filter_map(|(event, range)| match event {
+                pulldown_cmark::Event::Start(pulldown_cmark::Tag::CodeBlock(_))
+                | pulldown_cmark::Event::Code(_) => Some(range),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Markdown preprocessed for rendering (see `RenderConfig::preprocess_markdown`)
+struct Preprocessed {
+    markdown: String,
+    // where in `markdown` the HTML replacing parts of the source ends, and how much further
+    // from the start everything after it is than in the source
+    shifts: Vec<(usize, usize)>,
+}
+
+impl Preprocessed {
+    /// Byte range in the source of one in `markdown` (outside of the replaced parts)
+    fn source_range(&self, range: Range<usize>) -> Range<usize> {
+        let shift = |offset| {
+            self.shifts
+                .iter()
+                .take_while(|(end, _)| *end <= offset)
+                .last()
+                .map_or(0, |(_, shift)| *shift)
+        };
+        (range.start - shift(range.start))..(range.end - shift(range.end))
+    }
+}
+
+/// Task list items of a markdown `body`, with the byte ranges of their markers (`[ ]`, `[x]`)
+///
+/// Parsed like `Parsed::render` does with all markdown extensions, so tasks are indexed
+/// even if the tasklists extension is disabled, which only makes them render as plain list items.
+fn parse_tasks(body: &str) -> Vec<(Task, Range<usize>)> {
+    use pulldown_cmark::{Event, Tag};
+
+    let config = RenderConfig::default();
+    let preprocessed = config.preprocess_markdown(body);
+    let mut tasks: Vec<(Task, Range<usize>)> = vec![];
+    // is the text of the last task still being collected
+    let mut in_task = false;
+    for (event, range) in config
+        .new_markdown_parser(&preprocessed.markdown)
+        .into_offset_iter()
+    {
+        match &event {
+            Event::TaskListMarker(done) => {
+                let task = Task {
+                    index: tasks.len(),
+                    done: *done,
+                    text: String::new(),
+                };
+                tasks.push((task, preprocessed.source_range(range)));
+                in_task = true;
+            }
+            Event::Text(text) | Event::Code(text) if in_task => {
+                tasks.last_mut().expect("task").0.text.push_str(text)
+            }
+            Event::SoftBreak | Event::HardBreak if in_task => {
+                tasks.last_mut().expect("task").0.text.push(' ')
+            }
+            Event::End(Tag::Item) | Event::Start(Tag::List(_)) => in_task = false,
+            _ => {}
+        }
+    }
+    for (task, _) in &mut tasks {
+        task.text = task.text.trim().to_owned();
+    }
+    tasks
+}
+
+/// Checkbox of a task list item, clickable if it's one of `Parsed::tasks`
+///
+/// Clicking it toggles the task with `Parsed::with_task_done`.
+fn task_checkbox_html(page_id: IdRef, task: Option<&Task>, done: bool) -> String {
+    let checked = if done { " checked" } else { "" };
+    if let Some(task) = task {
+        // enabled by javascript, only where it makes sense
+        format!(
+            r#"<input type="checkbox" class="task-checkbox" data-page-id="{}" data-task="{}" data-task-text="{}" disabled{}>"#,
+            util::escape_xml(page_id),
+            task.index,
+            util::escape_xml(&task.text),
+            checked
+        )
+    } else {
+        format!(r#"<input type="checkbox" disabled{}>"#, checked)
+    }
+}
+
+/// `id` of a heading with a given (plain) `text`, eg. `step-2-restart-the-server`
+///
+/// Used for both heading anchors, and `[[Page#Section]]` links to them.
+pub fn heading_slug(text: &str) -> String {
+    let mut slug = String::new();
+    for ch in text.trim().chars().flat_map(char::to_lowercase) {
+        if ch.is_alphanumeric() {
+            slug.push(ch);
+        } else if (ch.is_whitespace() || ch == '-' || ch == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        "section".to_owned()
+    } else {
+        slug.to_owned()
+    }
+}
+
+/// Give all headings `id`s, unique within the page (and its UI), and collect them into `headings`
+fn add_heading_ids<'a>(
+    events: impl Iterator<Item = pulldown_cmark::Event<'a>>,
+    headings: &mut Vec<Heading>,
+) -> Vec<pulldown_cmark::Event<'a>> {
+    use pulldown_cmark::{Event, Tag};
+
+    let mut out = vec![];
+    let mut used: HashSet<String> = crate::render::ELEMENT_IDS
+        .iter()
+        .map(|id| (*id).to_owned())
+        .collect();
+    // position of the start tag, and text of the heading being collected
+    let mut current: Option<(usize, String)> = None;
+    for event in events {
+        match &event {
+            Event::Start(Tag::Heading(_)) => {
+                current = Some((out.len(), String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, heading_text)) = current.as_mut() {
+                    heading_text.push_str(text);
+                }
+            }
+            Event::End(Tag::Heading(level)) => {
+                if let Some((start, text)) = current.take() {
+                    let text = crate::wikilink::plain_text(text.trim());
+                    let slug = heading_slug(&text);
+                    let mut id = slug.clone();
+                    let mut i = 0;
+                    while !used.insert(id.clone()) {
+                        i += 1;
+                        id = format!("{}-{}", slug, i);
+                    }
+                    out[start] = Event::Html(
+                        format!(r#"<h{} id="{}">"#, level, util::escape_xml(&id)).into(),
+                    );
+                    out.push(Event::Html(format!("</h{}>\n", level).into()));
+                    headings.push(Heading {
+                        level: *level,
+                        id,
+                        text,
+                    });
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+    out
+}
 
 #[derive(Debug, Default, Clone)]
 pub struct Source(String);
 
+impl From<String> for Source {
+    fn from(s: String) -> Self {
+        Source(s)
+    }
+}
+
+/// Value of a custom metadata field (a `key: value` header)
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataValue {
+    Bool(bool),
+    Number(f64),
+    Date(NaiveDate),
+    Text(String),
+    List(Vec<MetadataValue>),
+}
+
+/// Custom metadata fields of a page, by (lowercase) key
+pub type Metadata = BTreeMap<String, MetadataValue>;
+
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().or_else(|| {
+        DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|t| t.naive_local().date())
+    })
+}
+
+impl MetadataValue {
+    pub fn parse(s: &str) -> Self {
+        let s = s.trim();
+        if let Ok(b) = s.parse::<bool>() {
+            MetadataValue::Bool(b)
+        } else if let Ok(n) = s.parse::<f64>() {
+            MetadataValue::Number(n)
+        } else if let Some(date) = parse_date(s) {
+            MetadataValue::Date(date)
+        } else {
+            MetadataValue::Text(s.to_owned())
+        }
+    }
+
+    fn from_yaml(value: &serde_yaml::Value) -> Option<Self> {
+        Some(match value {
+            serde_yaml::Value::Bool(b) => MetadataValue::Bool(*b),
+            serde_yaml::Value::Number(n) => MetadataValue::Number(n.as_f64()?),
+            serde_yaml::Value::String(s) => Self::parse(s),
+            serde_yaml::Value::Sequence(seq) => {
+                MetadataValue::List(seq.iter().filter_map(Self::from_yaml).collect())
+            }
+            _ => return None,
+        })
+    }
+
+    /// Compare with a value given as a string (eg. in a query), interpreted as the same type
+    ///
+    /// Text is compared case-insensitively. Lists are not comparable.
+    pub fn partial_cmp_str(&self, s: &str) -> Option<Ordering> {
+        let s = s.trim();
+        match self {
+            MetadataValue::Bool(b) => s.parse::<bool>().ok().map(|other| b.cmp(&other)),
+            MetadataValue::Number(n) => s
+                .parse::<f64>()
+                .ok()
+                .and_then(|other| n.partial_cmp(&other)),
+            MetadataValue::Date(date) => parse_date(s).map(|other| date.cmp(&other)),
+            MetadataValue::Text(text) => Some(text.to_lowercase().cmp(&s.to_lowercase())),
+            MetadataValue::List(_) => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Parsed {
+    #[allow(dead_code)]
     pub source: Source,
     pub source_body: String,
-    pub html: String,
     pub headers: Headers,
     pub tags: HashSet<Tag>,
     pub title: String,
+    pub tasks: Vec<Task>,
+}
+
+/// Page rendered to HTML (see `Parsed::render`)
+#[derive(Debug, Clone, Default)]
+pub struct Rendered {
+    pub html: String,
+    pub headings: Vec<Heading>,
+}
+
+/// Heading of a page, for its table of contents
+#[derive(Debug, Clone)]
+pub struct Heading {
+    pub level: u32,
+    // anchor, as in `#id` (see `heading_slug`)
+    pub id: String,
+    pub text: String,
+}
+
+/// Task list item (`- [ ] ...`) of a page
+#[derive(Debug, Clone)]
+pub struct Task {
+    // position among task list items of the page, as used by `Parsed::with_task_done`
+    pub index: usize,
+    pub done: bool,
+    // plain text of the item, without nested lists
+    pub text: String,
+}
+
+/// Format of the block holding page headers
+///
+/// Pages are always written back in the format they were read in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeadersFormat {
+    /// `<!--- key: value -->` block
+    #[default]
+    HtmlComment,
+    /// `---` delimited YAML front matter (as used by Jekyll, Hugo, Obsidian, ...)
+    YamlFrontMatter,
 }
 
-fn split_headers_and_body(source: &Source) -> (&str, &str) {
+fn split_headers_and_body(source: &Source) -> (HeadersFormat, &str, &str) {
     lazy_static! {
         static ref RE: regex::Regex =
             regex::RegexBuilder::new(r"\A[[:space:]]*<!--+(.*?)--+>(.*)\z")
@@ -40,25 +427,94 @@ fn split_headers_and_body(source: &Source) -> (&str, &str) {
                 .dot_matches_new_line(true)
                 .build()
                 .unwrap();
+        static ref YAML_RE: regex::Regex =
+            regex::RegexBuilder::new(r"\A---[ \t]*\n(?:(.*?)\n)?---[ \t]*(?:\n|\z)(.*)\z")
+                .dot_matches_new_line(true)
+                .build()
+                .unwrap();
     }
 
     if let Some(cap) = RE.captures_iter(&source.0).next() {
         (
+            HeadersFormat::HtmlComment,
             // important: trimming headers, prevent them from accumulating newlines in the output
             // during rewrites
             cap.get(1).expect("be there").as_str().trim(),
             cap.get(2).expect("be there").as_str(),
         )
+    } else if let Some(cap) = YAML_RE.captures_iter(&source.0).next().filter(|cap| {
+        // not just a page starting with a horizontal rule
+        let yaml = cap.get(1).map(|m| m.as_str()).unwrap_or("");
+        // tagwiki's own headers aren't quoted, so eg. an author with a colon isn't valid YAML
+        parse_front_matter(yaml).is_some()
+            || yaml
+                .lines()
+                .any(|line| line.starts_with(&format!("{}:", TAGWIKI_PAGE_ID_KEY)))
+    }) {
+        (
+            HeadersFormat::YamlFrontMatter,
+            cap.get(1).map(|m| m.as_str().trim()).unwrap_or(""),
+            cap.get(2).expect("be there").as_str(),
+        )
     } else {
-        ("", &source.0)
+        (HeadersFormat::HtmlComment, "", &source.0)
     }
 }
 
+fn parse_front_matter(yaml: &str) -> Option<serde_yaml::Mapping> {
+    serde_yaml::from_str(yaml).ok()
+}
+
+fn front_matter_str<'a>(front_matter: &'a serde_yaml::Mapping, key: &str) -> Option<&'a str> {
+    front_matter
+        .get(&serde_yaml::Value::String(key.into()))
+        .and_then(|value| value.as_str())
+}
+
+/// Parse `date` of the front matter, which can be just a date, or a date with time
+fn parse_front_matter_date(front_matter: &serde_yaml::Mapping) -> Option<DateTime<FixedOffset>> {
+    let value = front_matter_str(front_matter, "date")?.trim();
+
+    DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S %z"))
+        .ok()
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+            let time = Local
+                .from_local_datetime(&date.and_hms(0, 0, 0))
+                .earliest()?;
+            Some(time.with_timezone(time.offset()))
+        })
+}
+
+/// Parse `tags` of the front matter: either a list, or a space/comma separated string
+fn parse_front_matter_tags(front_matter: &serde_yaml::Mapping) -> Vec<Tag> {
+    let tags: Vec<&str> = match front_matter.get(&serde_yaml::Value::String("tags".into())) {
+        Some(serde_yaml::Value::Sequence(seq)) => {
+            seq.iter().filter_map(|value| value.as_str()).collect()
+        }
+        Some(serde_yaml::Value::String(s)) => s
+            .split(|ch: char| ch.is_whitespace() || ch == ',')
+            .collect(),
+        _ => vec![],
+    };
+
+    tags.into_iter()
+        .map(|tag| tag.trim().trim_start_matches('#').to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct Headers {
     pub id: String,
     pub creation_time: chrono::DateTime<FixedOffset>,
     pub modification_time: chrono::DateTime<FixedOffset>,
+    /// Who made the last modification, if known
+    pub modified_by: Option<String>,
+    /// Short description of the last modification, if known
+    pub change_summary: Option<String>,
+    pub format: HeadersFormat,
     pub other: String,
 }
 
@@ -68,15 +524,20 @@ impl Default for Headers {
             id: util::random_string(16),
             creation_time: util::now(),
             modification_time: util::now(),
+            modified_by: None,
+            change_summary: None,
+            format: HeadersFormat::default(),
             other: "".into(),
         }
     }
 }
 impl Headers {
-    fn parse(headers_str: &str, source: &Source) -> Headers {
+    fn parse(format: HeadersFormat, headers_str: &str, source: &Source) -> Headers {
         let mut id = None;
         let mut creation = None;
         let mut modification = None;
+        let mut modified_by = None;
+        let mut change_summary = None;
         let mut other = String::new();
 
         for line in headers_str.lines() {
@@ -96,15 +557,21 @@ impl Headers {
                             let time = chrono::DateTime::<FixedOffset>::parse_from_rfc3339(value);
                             modification = time.ok();
                         }
+                        TAGWIKI_MODIFIED_BY_KEY => {
+                            modified_by = Some(value.to_owned());
+                        }
+                        TAGWIKI_CHANGE_SUMMARY_KEY => {
+                            change_summary = Some(value.to_owned());
+                        }
                         _ => {
                             other.push_str(line);
-                            other.push_str("\n")
+                            other.push('\n')
                         }
                     }
                 }
                 _ => {
                     other.push_str(line);
-                    other.push_str("\n")
+                    other.push('\n')
                 }
             }
         }
@@ -116,22 +583,73 @@ impl Headers {
             hex::encode(&res.as_slice()[0..16])
         });
 
-        let creation: DateTime<chrono::offset::FixedOffset> =
-            creation.unwrap_or_else(|| util::now());
+        let creation = creation.or_else(|| {
+            if format == HeadersFormat::YamlFrontMatter {
+                parse_front_matter(&other)
+                    .and_then(|front_matter| parse_front_matter_date(&front_matter))
+            } else {
+                None
+            }
+        });
+        let creation: DateTime<chrono::offset::FixedOffset> = creation.unwrap_or_else(util::now);
 
-        let modification = modification.unwrap_or_else(|| util::now());
+        let modification = modification.unwrap_or_else(util::now);
 
         Self {
             other,
             id,
             creation_time: creation,
             modification_time: modification,
+            modified_by,
+            change_summary,
+            format,
+        }
+    }
+
+    /// Custom metadata fields: all headers except the ones managed by tagwiki
+    pub fn metadata(&self) -> Metadata {
+        match self.format {
+            HeadersFormat::HtmlComment => self
+                .other
+                .lines()
+                .filter_map(|line| {
+                    let mut split = line.splitn(2, ':');
+                    let key = split.next()?.trim();
+                    let value = split.next()?;
+                    if key.is_empty() {
+                        return None;
+                    }
+                    Some((key.to_lowercase(), MetadataValue::parse(value)))
+                })
+                .collect(),
+            HeadersFormat::YamlFrontMatter => self
+                .front_matter()
+                .map(|front_matter| {
+                    front_matter
+                        .iter()
+                        .filter_map(|(key, value)| {
+                            Some((
+                                key.as_str()?.to_lowercase(),
+                                MetadataValue::from_yaml(value)?,
+                            ))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Values of the YAML front matter, except the ones managed by tagwiki
+    fn front_matter(&self) -> Option<serde_yaml::Mapping> {
+        if self.format == HeadersFormat::YamlFrontMatter {
+            parse_front_matter(&self.other)
+        } else {
+            None
         }
     }
 
     fn to_markdown_string(&self) -> String {
-        "<!---\n".to_string()
-            + &format!("{}: {}\n", TAGWIKI_PAGE_ID_KEY, self.id)
+        let mut tagwiki_headers = format!("{}: {}\n", TAGWIKI_PAGE_ID_KEY, self.id)
             + &format!(
                 "{}: {}\n",
                 TAGWIKI_CREATION_TIME_KEY,
@@ -141,9 +659,24 @@ impl Headers {
                 "{}: {}\n",
                 TAGWIKI_MODIFICATION_TIME_KEY,
                 self.modification_time.to_rfc3339()
-            )
-            + &self.other
-            + "-->\n"
+            );
+        for (key, value) in &[
+            (TAGWIKI_MODIFIED_BY_KEY, &self.modified_by),
+            (TAGWIKI_CHANGE_SUMMARY_KEY, &self.change_summary),
+        ] {
+            if let Some(value) = value {
+                tagwiki_headers += &format!("{}: {}\n", key, value);
+            }
+        }
+        match self.format {
+            HeadersFormat::HtmlComment => {
+                "<!---\n".to_string() + &tagwiki_headers + &self.other + "-->\n"
+            }
+            // keep user's own front matter first, where other tools expect it
+            HeadersFormat::YamlFrontMatter => {
+                "---\n".to_string() + &self.other + &tagwiki_headers + "---\n"
+            }
+        }
     }
 }
 
@@ -153,7 +686,12 @@ fn parse_tags(body: &str) -> Vec<String> {
             regex::Regex::new(r"#([a-zA-Z0-9_\-]+)").expect("correct regex");
     }
 
-    RE.captures_iter(&body)
+    RE.captures_iter(body)
+        .filter(|m| {
+            // not `[[Page#Section]]` nor `[link](#section)`
+            let before = &body[..m.get(0).expect("a value").start()];
+            !before.ends_with(|ch: char| ch.is_alphanumeric()) && !before.ends_with("](")
+        })
         .map(|m| m.get(1).expect("a value").as_str().to_lowercase())
         .collect()
 }
@@ -165,17 +703,17 @@ fn parse_title(body: &str) -> String {
     }
 
     let title = RE
-        .captures_iter(&body)
+        .captures_iter(body)
         .map(|m| m.get(1).expect("a value").as_str().trim().to_string())
         .next()
         .unwrap_or_else(|| "".to_string());
-    let title = if title == "" {
+    let title = if title.is_empty() {
         lazy_static! {
             static ref RE: regex::Regex =
                 regex::Regex::new(r"[[:space:]]*(.*?)[\.\n]").expect("correct regex");
         }
 
-        RE.captures_iter(&body)
+        RE.captures_iter(body)
             .map(|m| m.get(1).expect("a value").as_str().trim().to_string())
             .next()
             .unwrap_or_else(|| "".to_string())
@@ -183,7 +721,7 @@ fn parse_title(body: &str) -> String {
         title
     };
 
-    if title == "" {
+    if title.is_empty() {
         "Untitled".to_string()
     } else {
         title
@@ -191,51 +729,166 @@ fn parse_title(body: &str) -> String {
 }
 
 impl Parsed {
-    pub fn id(&self) -> IdRef {
+    pub fn id(&self) -> IdRef<'_> {
         self.headers.id.as_str()
     }
 
     pub fn new(body: &str) -> Parsed {
+        Self::new_with_id(crate::util::random_string(16), body)
+    }
+
+    /// Can `id` be used for a new page (as one chosen upfront by the editor)
+    ///
+    /// Only the format is checked, and it's the one of `Parsed::new`'s random IDs,
+    /// so it is safe in paths, URLs and headers.
+    pub fn is_valid_new_id(id: IdRef) -> bool {
+        !id.is_empty() && id.len() <= 64 && id.chars().all(|ch| ch.is_ascii_alphanumeric())
+    }
+
+    /// New page with an ID chosen upfront (eg. to attach files before it was saved)
+    pub fn new_with_id(id: Id, body: &str) -> Parsed {
         let headers = Headers {
-            id: crate::util::random_string(16),
+            id,
             ..Headers::default()
         };
         Self::from_headers_and_body(headers, body.to_owned())
     }
 
     pub fn from_full_source(source: Source) -> Parsed {
-        let (headers, body) = split_headers_and_body(&source);
-        let headers = Headers::parse(headers, &source);
+        let (format, headers, body) = split_headers_and_body(&source);
+        let headers = Headers::parse(format, headers, &source);
 
         Self::from_headers_and_body(headers, body.to_owned())
     }
 
     fn from_headers_and_body(headers: Headers, body: String) -> Parsed {
         let source = headers.to_markdown_string() + &body;
-        let parser = pulldown_cmark::Parser::new(&body);
-        let title = parse_title(&body);
-        let mut html_output = String::new();
-        pulldown_cmark::html::push_html(&mut html_output, parser);
+        let front_matter = headers.front_matter();
+        let tasks = parse_tasks(&body)
+            .into_iter()
+            .map(|(task, _)| task)
+            .collect();
+        let title = front_matter
+            .as_ref()
+            .and_then(|front_matter| front_matter_str(front_matter, "title"))
+            .map(|title| title.trim().to_owned())
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| parse_title(&body));
 
-        let tags = parse_tags(&body);
+        let mut tags = parse_tags(&body);
+        if let Some(front_matter) = front_matter.as_ref() {
+            tags.extend(parse_front_matter_tags(front_matter));
+        }
 
         Parsed {
             headers,
-            html: html_output,
             source_body: body,
             source: Source(source),
             tags: tags.into_iter().collect(),
             title,
+            tasks,
         }
     }
 
-    pub fn update_modification_time(&mut self) {
+    /// Render the page to HTML
+    ///
+    /// Wiki links in it are only placeholders (see `wikilink::resolve`).
+    pub fn render(&self, config: &RenderConfig) -> Rendered {
+        let preprocessed = config.preprocess_markdown(&self.source_body);
+        // matched by where they are in the source, as without some markdown extensions
+        // tasks might not be the ones `parse_tasks` found
+        let tasks: HashMap<usize, Task> = parse_tasks(&self.source_body)
+            .into_iter()
+            .map(|(task, range)| (range.start, task))
+            .collect();
+        let parser = config
+            .new_markdown_parser(&preprocessed.markdown)
+            .into_offset_iter()
+            .map(|(event, range)| {
+                if let pulldown_cmark::Event::TaskListMarker(done) = event {
+                    let task = tasks.get(&preprocessed.source_range(range).start);
+                    pulldown_cmark::Event::Html(task_checkbox_html(self.id(), task, done).into())
+                } else {
+                    crate::sanitize::Generated::mark_page_html(event)
+                }
+            });
+        let mut diagrams = vec![];
+        let mut headings = vec![];
+        let mut generated = crate::sanitize::Generated::new();
+        let events = add_heading_ids(parser, &mut headings);
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(
+            &mut html,
+            generated
+                .extract(
+                    crate::wikilink::expand_events(
+                        crate::highlight::highlight_events(
+                            events.into_iter(),
+                            &config.diagram_commands,
+                            &mut diagrams,
+                        )
+                        .into_iter(),
+                    )
+                    .into_iter(),
+                )
+                .into_iter(),
+        );
+        let html = if let Some(sanitize_config) = config.sanitize.as_ref() {
+            crate::sanitize::sanitize(&html, sanitize_config)
+        } else {
+            html
+        };
+        Rendered {
+            html: crate::diagram::insert_rendered(&generated.insert(&html), &diagrams),
+            headings,
+        }
+    }
+
+    /// `render` on a thread where blocking is fine, as diagrams are rendered by external commands
+    pub async fn render_blocking(&self, config: &RenderConfig) -> Result<Rendered> {
+        let (page, config) = (self.clone(), config.clone());
+        Ok(tokio::task::spawn_blocking(move || page.render(&config)).await?)
+    }
+
+    /// Record that the page was just modified, by whom (if known), and how
+    pub fn record_modification(&mut self, modified_by: Option<String>, change_summary: String) {
         self.headers.modification_time = util::now();
+        self.headers.modified_by = modified_by;
+        self.headers.change_summary = Some(change_summary);
     }
 
     pub fn with_new_source_body(&self, new_body_source: &str) -> Self {
         Self::from_headers_and_body(self.headers.clone(), new_body_source.to_owned())
     }
+
+    /// New version of the page, with a timestamped list item appended to the body
+    pub fn with_appended_entry(&self, time: DateTime<FixedOffset>, text: &str) -> Self {
+        let mut body = self.source_body.clone();
+        if !body.is_empty() && !body.ends_with('\n') {
+            body.push('\n');
+        }
+        body.push_str(&format!("- {} ", time.format("%Y-%m-%d %H:%M")));
+        // continuation lines are indented, to stay a part of the list item
+        body.push_str(&text.trim().lines().collect::<Vec<_>>().join("\n  "));
+        body.push('\n');
+        self.with_new_source_body(&body)
+    }
+
+    /// New version of the page, with the `index`-th task list item (`- [ ] ...`) done or not
+    ///
+    /// Fails if the text of that item is not `text`, eg. when the page was edited in the meantime.
+    pub fn with_task_done(&self, index: usize, text: &str, done: bool) -> Result<Self> {
+        let (task, range) = parse_tasks(&self.source_body)
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| format_err!("Task not found"))?;
+        if task.text != text {
+            bail!("The task has changed since, reload the page");
+        }
+        let mut body = self.source_body.clone();
+        body.replace_range(range, if done { "[x]" } else { "[ ]" });
+        Ok(self.with_new_source_body(&body))
+    }
 }
 
 #[test]
@@ -248,12 +901,13 @@ fn split_headers_and_body_test() -> Result<()> {
 c: d -->banana"#
             .into(),
     );
-    let (headers, body) = split_headers_and_body(&s);
+    let (format, headers, body) = split_headers_and_body(&s);
 
+    assert_eq!(format, HeadersFormat::HtmlComment);
     assert_eq!(
         headers,
-        r#" a: b
-c: d "#
+        r#"a: b
+c: d"#
     );
     assert_eq!(body, "banana");
 
@@ -284,3 +938,211 @@ tagwiki-id: 123
     assert_eq!(page.id(), "xyz");
     Ok(())
 }
+
+#[test]
+fn yaml_front_matter_test() -> Result<()> {
+    let page = Parsed::from_full_source(Source(
+        r#"---
+title: Weekly sync
+tags: [work, Meeting]
+date: 2020-05-15
+---
+# Notes #journal
+"#
+        .to_owned(),
+    ));
+
+    assert_eq!(page.headers.format, HeadersFormat::YamlFrontMatter);
+    assert_eq!(page.title, "Weekly sync");
+    assert_eq!(
+        page.tags,
+        ["work", "meeting", "journal"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect()
+    );
+    assert_eq!(
+        page.headers.creation_time.date().naive_local(),
+        NaiveDate::from_ymd(2020, 5, 15)
+    );
+    assert_eq!(page.source_body, "# Notes #journal\n");
+
+    // saving keeps the format, and re-reading the result yields the same page
+    let saved = page.headers.to_markdown_string() + &page.source_body;
+    assert!(saved.starts_with("---\ntitle: Weekly sync\n"));
+    let reread = Parsed::from_full_source(Source(saved.clone()));
+    assert_eq!(reread.id(), page.id());
+    assert_eq!(reread.title, page.title);
+    assert_eq!(
+        reread.headers.to_markdown_string() + &reread.source_body,
+        saved
+    );
+
+    // even if tagwiki's own headers make it invalid YAML
+    let saved = saved.replace("title: ", "tagwiki-modified-by: Alice: Smith\ntitle: ");
+    let reread = Parsed::from_full_source(Source(saved));
+    assert_eq!(reread.headers.format, HeadersFormat::YamlFrontMatter);
+    assert_eq!(reread.source_body, page.source_body);
+    Ok(())
+}
+
+#[test]
+fn horizontal_rule_not_front_matter_test() -> Result<()> {
+    for source in &[
+        "---\nSome *intro*, not YAML: [really\n---\n# Page\n",
+        "---\njust text\n---\n# Page\n",
+        "---\n---\n# Page\n",
+    ] {
+        let page = Parsed::from_full_source(Source(source.to_string()));
+        assert_eq!(page.headers.format, HeadersFormat::HtmlComment);
+        assert_eq!(page.source_body, *source);
+        assert_eq!(page.title, "Page");
+    }
+    Ok(())
+}
+
+#[test]
+fn metadata_test() -> Result<()> {
+    let page = Parsed::from_full_source(Source(
+        r#"<!---
+tagwiki-page-id: xyz
+Status: open
+owner: alice
+due: 2026-11-01
+estimate: 3.5
+-->
+body"#
+            .to_owned(),
+    ));
+
+    let metadata = page.headers.metadata();
+    assert_eq!(metadata.len(), 4);
+    assert_eq!(metadata["status"], MetadataValue::Text("open".into()));
+    assert_eq!(
+        metadata["due"],
+        MetadataValue::Date(NaiveDate::from_ymd(2026, 11, 1))
+    );
+    assert_eq!(
+        metadata["due"].partial_cmp_str("2026-12-01"),
+        Some(Ordering::Less)
+    );
+    assert_eq!(
+        metadata["estimate"].partial_cmp_str("3.5"),
+        Some(Ordering::Equal)
+    );
+    assert_eq!(
+        metadata["owner"].partial_cmp_str("Alice"),
+        Some(Ordering::Equal)
+    );
+    Ok(())
+}
+
+#[test]
+fn with_appended_entry_test() {
+    let page = Parsed::new("# Inbox #inbox");
+    let time = DateTime::parse_from_rfc3339("2026-10-19T14:05:00+02:00").unwrap();
+    let page = page.with_appended_entry(time, "buy milk\n");
+    let page = page.with_appended_entry(time, "call Bob\nabout the #project\n");
+    assert_eq!(
+        page.source_body,
+        "# Inbox #inbox\n- 2026-10-19 14:05 buy milk\n- 2026-10-19 14:05 call Bob\n  about the #project\n"
+    );
+    assert!(page.tags.contains("project"));
+}
+
+#[test]
+fn with_task_done_test() -> Result<()> {
+    let page = Parsed::new_with_id(
+        "abc".into(),
+        "# Todo\n\n```\n- [ ] not a task\n```\n\n- [ ] first\n- [x] second\n  - [ ] nested\n",
+    );
+    assert!(page.render(&RenderConfig::default()).html.contains(
+        r#"<input type="checkbox" class="task-checkbox" data-page-id="abc" data-task="1" data-task-text="second" disabled checked>"#
+    ));
+
+    let page = page
+        .with_task_done(0, "first", true)?
+        .with_task_done(1, "second", false)?;
+    let page = page.with_task_done(2, "nested", true)?;
+    assert_eq!(
+        page.source_body,
+        "# Todo\n\n```\n- [ ] not a task\n```\n\n- [x] first\n- [ ] second\n  - [x] nested\n"
+    );
+    assert!(page.with_task_done(3, "", true).is_err());
+    assert!(page.with_task_done(1, "first", true).is_err());
+    assert_eq!(
+        page.tasks
+            .iter()
+            .map(|task| (task.done, task.text.as_str()))
+            .collect::<Vec<_>>(),
+        vec![(true, "first"), (false, "second"), (true, "nested")]
+    );
+    Ok(())
+}
+
+#[test]
+fn task_indices_test() -> Result<()> {
+    // not tasks once the math is marked up, nor rendered as ones
+    let page = Parsed::new_with_id(
+        "abc".into(),
+        "$$\n- [ ] math\n$$\n\n- [ ] $x$ first[^1]\n\n[^1]: - [ ] in a footnote\n",
+    );
+    assert_eq!(
+        page.tasks
+            .iter()
+            .map(|task| task.text.as_str())
+            .collect::<Vec<_>>(),
+        vec!["x first", "in a footnote"]
+    );
+    let html = page.render(&RenderConfig::default()).html;
+    assert!(html.contains(r#"data-task="1" data-task-text="in a footnote""#));
+    assert!(!html.contains(r#"data-task="2""#));
+    let page = page.with_task_done(1, "in a footnote", true)?;
+    assert!(page.source_body.ends_with("[^1]: - [x] in a footnote\n"));
+
+    // without footnotes, the indices of the tasks still are the same
+    let config = RenderConfig {
+        markdown_extensions: vec![MarkdownExtension::Tasklists],
+        ..RenderConfig::default()
+    };
+    let html = page.render(&config).html;
+    assert!(html.contains(r#"data-task="0" data-task-text="x first""#));
+    assert!(!html.contains(r#"data-task="1""#));
+    Ok(())
+}
+
+#[test]
+fn heading_ids_test() {
+    let page = Parsed::new_with_id(
+        "abc".into(),
+        "# Runbook #ops\n\n## Step 1: `restart` it\n\n## Step 1: restart it\n\n### See [[Other#Setup]]\n\n[Back](#runbook-ops)\n",
+    );
+    let rendered = page.render(&RenderConfig::default());
+    assert!(rendered
+        .html
+        .contains(r#"<h1 id="runbook-ops">Runbook #ops</h1>"#));
+    assert!(rendered.html.contains(r#"<h2 id="step-1-restart-it-1">"#));
+    assert!(rendered.html.contains(r##"href="/_link?to=Other#setup""##));
+    assert_eq!(
+        rendered
+            .headings
+            .iter()
+            .map(|heading| (heading.level, heading.id.as_str(), heading.text.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, "runbook-ops", "Runbook #ops"),
+            (2, "step-1-restart-it", "Step 1: restart it"),
+            (2, "step-1-restart-it-1", "Step 1: restart it"),
+            (3, "see-othersetup", "See Other#Setup"),
+        ]
+    );
+    assert_eq!(page.tags, ["ops"].iter().map(|t| t.to_string()).collect());
+}
+
+#[test]
+fn is_valid_new_id_test() {
+    assert!(Parsed::is_valid_new_id(&crate::util::random_string(16)));
+    assert!(!Parsed::is_valid_new_id(""));
+    assert!(!Parsed::is_valid_new_id("../victim"));
+    assert!(!Parsed::is_valid_new_id("abc\ntagwiki-page-id: xyz"));
+}
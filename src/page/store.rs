@@ -1,26 +1,45 @@
 use crate::page::{self, Id};
-use anyhow::{format_err, Result};
+use anyhow::{bail, format_err, Result};
 use async_trait::async_trait;
+use chrono::prelude::*;
 use std::collections::HashMap;
 use std::sync;
 
 pub mod fs;
 pub use fs::FsStore;
 
+/// Page that was deleted, but can still be restored
+#[derive(Debug, Clone)]
+pub struct Trashed {
+    pub id: Id,
+    pub title: String,
+    pub deletion_time: chrono::DateTime<FixedOffset>,
+}
+
 #[async_trait]
 pub trait Store {
     async fn get(&self, id: Id) -> Result<page::Parsed>;
     async fn put(&self, page: &page::Parsed) -> Result<()>;
+    /// Move the page to the trash
     async fn delete(&self, id: Id) -> Result<()>;
     async fn iter<'s>(&'s self) -> Result<Box<dyn Iterator<Item = Id> + 's>>;
+    async fn trashed(&self) -> Result<Vec<Trashed>>;
+    async fn restore(&self, id: Id) -> Result<page::Parsed>;
+    /// Permanently remove a page from the trash
+    async fn purge(&self, id: Id) -> Result<()>;
 }
 
 #[async_trait]
 pub trait StoreMut {
     async fn get(&self, id: Id) -> Result<page::Parsed>;
     async fn put(&mut self, page: &page::Parsed) -> Result<()>;
+    /// Move the page to the trash
     async fn delete(&mut self, id: Id) -> Result<()>;
     async fn iter<'s>(&'s self) -> Result<Box<dyn Iterator<Item = Id> + 's>>;
+    async fn trashed(&self) -> Result<Vec<Trashed>>;
+    async fn restore(&mut self, id: Id) -> Result<page::Parsed>;
+    /// Permanently remove a page from the trash
+    async fn purge(&mut self, id: Id) -> Result<()>;
 }
 
 #[async_trait]
@@ -43,6 +62,18 @@ where
     async fn iter<'s>(&'s self) -> Result<Box<dyn Iterator<Item = Id> + 's>> {
         Store::iter(self).await
     }
+
+    async fn trashed(&self) -> Result<Vec<Trashed>> {
+        Store::trashed(self).await
+    }
+
+    async fn restore(&mut self, id: Id) -> Result<page::Parsed> {
+        Store::restore(self, id).await
+    }
+
+    async fn purge(&mut self, id: Id) -> Result<()> {
+        Store::purge(self, id).await
+    }
 }
 
 #[async_trait]
@@ -62,6 +93,18 @@ impl StoreMut for Box<dyn StoreMut + Send + Sync> {
     async fn iter<'s>(&'s self) -> Result<Box<dyn Iterator<Item = Id> + 's>> {
         (**self).iter().await
     }
+
+    async fn trashed(&self) -> Result<Vec<Trashed>> {
+        (**self).trashed().await
+    }
+
+    async fn restore(&mut self, id: Id) -> Result<page::Parsed> {
+        (**self).restore(id).await
+    }
+
+    async fn purge(&mut self, id: Id) -> Result<()> {
+        (**self).purge(id).await
+    }
 } /*
   impl<T> Store for sync::Arc<sync::Mutex<T>>
   where
@@ -119,11 +162,24 @@ where
                 .into_iter(),
         ))
     }
+
+    async fn trashed(&self) -> Result<Vec<Trashed>> {
+        self.read().await.trashed().await
+    }
+
+    async fn restore(&self, id: Id) -> Result<page::Parsed> {
+        self.write().await.restore(id).await
+    }
+
+    async fn purge(&self, id: Id) -> Result<()> {
+        self.write().await.purge(id).await
+    }
 } // impl Store for Arc<Mutex<InMemoryStore>> {}
 
 #[derive(Debug, Default)]
 pub struct InMemoryStore {
     page_by_id: HashMap<Id, page::Parsed>,
+    trash_by_id: HashMap<Id, (page::Parsed, chrono::DateTime<FixedOffset>)>,
 }
 
 impl InMemoryStore {
@@ -136,29 +192,60 @@ impl InMemoryStore {
 #[async_trait]
 impl StoreMut for InMemoryStore {
     async fn get(&self, id: Id) -> Result<page::Parsed> {
-        Ok(self
-            .page_by_id
+        self.page_by_id
             .get(&id)
             .cloned()
-            .ok_or_else(|| format_err!("Not found"))?)
+            .ok_or_else(|| format_err!("Not found"))
     }
 
     async fn put(&mut self, page: &page::Parsed) -> Result<()> {
-        *self
-            .page_by_id
-            .get_mut(&page.headers.id)
-            .ok_or_else(|| format_err!("Not found"))? = page.clone();
+        self.page_by_id
+            .insert(page.headers.id.clone(), page.clone());
 
         Ok(())
     }
 
     async fn delete(&mut self, id: Id) -> Result<()> {
-        self.page_by_id
+        let page = self
+            .page_by_id
             .remove(&id)
             .ok_or_else(|| format_err!("Not found"))?;
+        self.trash_by_id.insert(id, (page, crate::util::now()));
         Ok(())
     }
+
     async fn iter<'s>(&'s self) -> Result<Box<dyn Iterator<Item = Id> + 's>> {
         Ok(Box::new(self.page_by_id.keys().cloned()))
     }
+
+    async fn trashed(&self) -> Result<Vec<Trashed>> {
+        Ok(self
+            .trash_by_id
+            .values()
+            .map(|(page, deletion_time)| Trashed {
+                id: page.id().to_owned(),
+                title: page.title.clone(),
+                deletion_time: *deletion_time,
+            })
+            .collect())
+    }
+
+    async fn restore(&mut self, id: Id) -> Result<page::Parsed> {
+        if self.page_by_id.contains_key(&id) {
+            bail!("Page already exists");
+        }
+        let (page, _) = self
+            .trash_by_id
+            .remove(&id)
+            .ok_or_else(|| format_err!("Not found"))?;
+        self.page_by_id.insert(id, page.clone());
+        Ok(page)
+    }
+
+    async fn purge(&mut self, id: Id) -> Result<()> {
+        self.trash_by_id
+            .remove(&id)
+            .ok_or_else(|| format_err!("Not found"))?;
+        Ok(())
+    }
 }
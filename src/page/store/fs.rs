@@ -1,16 +1,41 @@
-use crate::page::{self, Id};
-use anyhow::{format_err, Context, Result};
+use crate::attachment::AttachmentStore;
+use crate::page::{self, store::Trashed, Id};
+use anyhow::{bail, format_err, Context, Result};
 use async_trait::async_trait;
+use chrono::prelude::*;
 use std::collections::HashMap;
 use std::ffi::OsString;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 
+/// Directory (relative to the `root_path`) deleted pages are moved to
+const TRASH_DIR: &str = ".trash";
+
+/// Extension of the files next to trashed pages, with the path they were deleted from
+const TRASHED_FROM_EXTENSION: &str = "from";
+
 #[derive(Debug, Default)]
 pub struct FsStore {
     root_path: PathBuf,
     id_to_path: HashMap<Id, PathBuf>,
     path_to_page: HashMap<PathBuf, page::Parsed>,
+    rename_on_title_change: bool,
+    trash_retention: Option<chrono::Duration>,
+}
+
+/// Rename `from` to `to`, failing if `to` exists already
+fn rename_no_replace(from: &Path, to: &Path) -> Result<()> {
+    std::fs::hard_link(from, to)
+        .map_err(|e| format_err!("Can't create {}: {}", to.display(), e))?;
+    std::fs::remove_file(from)?;
+    Ok(())
+}
+
+fn remove_file_if_exists(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
 }
 
 impl FsStore {
@@ -19,7 +44,21 @@ impl FsStore {
             root_path,
             ..Self::default()
         };
-        for entry in walkdir::WalkDir::new(&s.root_path) {
+        // tagwiki's own directories don't contain regular pages
+        let own_dirs = [
+            TRASH_DIR,
+            crate::attachment::ATTACHMENTS_DIR,
+            crate::draft::DRAFTS_DIR,
+        ];
+        for entry in walkdir::WalkDir::new(&s.root_path)
+            .into_iter()
+            .filter_entry(|entry| {
+                entry.depth() != 1
+                    || !own_dirs
+                        .iter()
+                        .any(|dir| entry.file_name() == std::ffi::OsStr::new(dir))
+            })
+        {
             match s.try_reading_page_from_entry_res(entry) {
                 Ok(Some((page, path))) => {
                     s.id_to_path.insert(page.headers.id.clone(), path.clone());
@@ -34,7 +73,127 @@ impl FsStore {
         Ok(s)
     }
 
-    fn title_to_new_rel_path(&self, title: &str) -> PathBuf {
+    /// Path of the file of a page
+    pub fn page_file_path(&self, id: page::IdRef) -> Option<PathBuf> {
+        self.id_to_path
+            .get(id)
+            .map(|path| self.root_path.join(path))
+    }
+
+    /// Rename page files to match their title whenever it changes
+    pub fn with_rename_on_title_change(self, rename_on_title_change: bool) -> Self {
+        Self {
+            rename_on_title_change,
+            ..self
+        }
+    }
+
+    /// Permanently remove pages that were in the trash for longer than `trash_retention`
+    pub fn with_trash_retention(self, trash_retention: Option<chrono::Duration>) -> Self {
+        let s = Self {
+            trash_retention,
+            ..self
+        };
+        if let Err(e) = s.purge_expired_trash() {
+            eprintln!("Error purging trash: {}", e);
+        }
+        s
+    }
+
+    fn trash_path(&self) -> PathBuf {
+        self.root_path.join(TRASH_DIR)
+    }
+
+    /// Path of the trashed page with a given `id` (coming from the outside)
+    fn trashed_page_path(&self, id: &str) -> Result<PathBuf> {
+        crate::util::check_path_component(id)?;
+        Ok(self.trash_path().join(id).with_extension("md"))
+    }
+
+    /// Read the trashed page with a given `id`, making sure it is the right one
+    fn read_trashed_page_by_id(&self, id: &str) -> Result<(page::Parsed, PathBuf)> {
+        let trashed_path = self.trashed_page_path(id)?;
+        if !trashed_path.exists() {
+            bail!("Not found");
+        }
+        let (page, _deletion_time) = self.read_trashed_page(&trashed_path)?;
+        if page.id() != id {
+            bail!(
+                "Trashed page {} has a different ID: {}",
+                trashed_path.display(),
+                page.id()
+            );
+        }
+        Ok((page, trashed_path))
+    }
+
+    fn read_trashed_page(&self, path: &Path) -> Result<(page::Parsed, DateTime<FixedOffset>)> {
+        let mut source = page::Source::default();
+        std::fs::File::open(path)?.read_to_string(&mut source.0)?;
+        // the modification time of a trashed file is set to the time of deletion
+        let deletion_time = DateTime::<Local>::from(std::fs::metadata(path)?.modified()?);
+
+        Ok((
+            page::Parsed::from_full_source(source),
+            deletion_time.with_timezone(deletion_time.offset()),
+        ))
+    }
+
+    fn read_trash(&self) -> Result<Vec<(page::Parsed, DateTime<FixedOffset>, PathBuf)>> {
+        let trash_path = self.trash_path();
+        if !trash_path.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut res = vec![];
+        for entry in std::fs::read_dir(trash_path)? {
+            let path = entry?.path();
+            if path.extension() != Some(&OsString::from("md")) {
+                continue;
+            }
+            // one bad file must not make the whole trash inaccessible
+            match self.read_trashed_page(&path) {
+                Ok((page, _)) if path.file_stem() != Some(page.id().as_ref()) => {
+                    eprintln!(
+                        "Skipping trashed page {} with a different ID: {}",
+                        path.display(),
+                        page.id()
+                    );
+                }
+                Ok((page, deletion_time)) => res.push((page, deletion_time, path)),
+                Err(e) => eprintln!("Error reading trashed page {}: {}", path.display(), e),
+            }
+        }
+        Ok(res)
+    }
+
+    fn purge_expired_trash(&self) -> Result<()> {
+        let retention = if let Some(retention) = self.trash_retention {
+            retention
+        } else {
+            return Ok(());
+        };
+
+        let now = crate::util::now();
+        for (page, deletion_time, path) in self.read_trash()? {
+            if now.signed_duration_since(deletion_time) > retention {
+                std::fs::remove_file(&path)?;
+                remove_file_if_exists(&path.with_extension(TRASHED_FROM_EXTENSION))?;
+                self.attachments().delete_all(page.id())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn attachments(&self) -> AttachmentStore {
+        AttachmentStore::new(&self.root_path)
+    }
+
+    /// Find a free path for a page with a given `title`
+    ///
+    /// The page is placed in `dir` (relative to `root_path`). `current` is the path
+    /// the page already occupies (if any), so it is not considered a collision.
+    fn title_to_new_rel_path(&self, dir: &Path, title: &str, current: Option<&Path>) -> PathBuf {
         let title = title.trim();
         let mut last_char_was_alphanum = false;
         let mut path_str = String::new();
@@ -55,13 +214,18 @@ impl FsStore {
         }
 
         let initial_title = path_str.to_lowercase();
-        let mut path = PathBuf::from(&initial_title);
+        let mut path = dir.join(&initial_title).with_extension("md");
         let mut i = 1;
-        while let Some(_) = self.path_to_page.get(&path.with_extension("md")) {
-            path = PathBuf::from(format!("{}-{}", &initial_title, i));
+        // files that are not (known) pages are not to be overwritten either
+        while (self.path_to_page.contains_key(&path) || self.root_path.join(&path).exists())
+            && Some(path.as_path()) != current
+        {
+            path = dir
+                .join(format!("{}-{}", &initial_title, i))
+                .with_extension("md");
             i += 1;
         }
-        path.with_extension("md")
+        path
     }
 
     fn try_reading_page_from_entry_res(
@@ -100,7 +264,15 @@ impl FsStore {
         )))
     }
 
-    async fn write_page_to_file(&self, rel_path: &Path, page: &page::Parsed) -> Result<()> {
+    /// Write `page` to the file at `rel_path` atomically
+    ///
+    /// Unless `replace`, it's an error if the file exists already.
+    async fn write_page_to_file(
+        &self,
+        rel_path: &Path,
+        page: &page::Parsed,
+        replace: bool,
+    ) -> Result<()> {
         let page = page.clone();
         use std::io::Write;
         let path = self.root_path.join(rel_path).with_extension("md");
@@ -115,7 +287,15 @@ impl FsStore {
             file.sync_data()?;
             drop(file);
 
-            std::fs::rename(tmp_path, path)?;
+            if replace {
+                std::fs::rename(tmp_path, path)?;
+            } else {
+                let res = rename_no_replace(&tmp_path, &path);
+                if res.is_err() {
+                    let _ = std::fs::remove_file(&tmp_path);
+                }
+                res?;
+            }
             Ok(())
         })
         .await??;
@@ -134,13 +314,51 @@ impl page::StoreMut for FsStore {
     }
 
     async fn put(&mut self, page: &page::Parsed) -> Result<()> {
-        let path = if let Some(path) = self.id_to_path.get(&page.headers.id) {
-            path.clone()
-        } else {
-            self.title_to_new_rel_path(&page.title)
+        let old_path = self.id_to_path.get(&page.headers.id).cloned();
+        let path = match old_path.as_ref() {
+            Some(old_path)
+                if self.rename_on_title_change
+                    && self
+                        .path_to_page
+                        .get(old_path)
+                        .map(|old_page| old_page.title != page.title)
+                        .unwrap_or(false) =>
+            {
+                let dir = old_path.parent().unwrap_or_else(|| Path::new(""));
+                self.title_to_new_rel_path(dir, &page.title, Some(old_path))
+            }
+            Some(old_path) => old_path.clone(),
+            None => self.title_to_new_rel_path(Path::new(""), &page.title, None),
         };
 
-        self.write_page_to_file(&path, &page).await?;
+        // new content is atomically written in place first, and only then the file
+        // is (atomically) renamed, so there is never more than one file of a page
+        self.write_page_to_file(old_path.as_ref().unwrap_or(&path), page, old_path.is_some())
+            .await?;
+        let path = match old_path {
+            Some(old_path) if old_path != path => {
+                match rename_no_replace(
+                    &self.root_path.join(&old_path),
+                    &self.root_path.join(&path),
+                ) {
+                    Ok(()) => {
+                        self.path_to_page.remove(&old_path);
+                        path
+                    }
+                    // the content is saved anyway, just under the old name
+                    Err(e) => {
+                        eprintln!(
+                            "Can't rename {} to {}: {}",
+                            old_path.display(),
+                            path.display(),
+                            e
+                        );
+                        old_path
+                    }
+                }
+            }
+            _ => path,
+        };
         self.id_to_path
             .insert(page.headers.id.clone(), path.clone());
         self.path_to_page.insert(path, page.clone());
@@ -153,13 +371,216 @@ impl page::StoreMut for FsStore {
             .get(&id)
             .cloned()
             .ok_or_else(|| format_err!("Not found"))?;
+        let trashed_path = self.trashed_page_path(&id)?;
+        std::fs::create_dir_all(self.trash_path())?;
+        std::fs::rename(self.root_path.join(&path), &trashed_path)?;
+        std::fs::File::options()
+            .write(true)
+            .open(&trashed_path)?
+            .set_modified(std::time::SystemTime::now())?;
+        std::fs::write(
+            trashed_path.with_extension(TRASHED_FROM_EXTENSION),
+            path.to_string_lossy().as_bytes(),
+        )?;
         self.path_to_page.remove(&path);
         self.id_to_path.remove(&id);
-        std::fs::remove_file(self.root_path.join(path))?;
+
+        self.purge_expired_trash()?;
         Ok(())
     }
 
     async fn iter<'s>(&'s self) -> Result<Box<dyn Iterator<Item = Id> + 's>> {
         Ok(Box::new(self.id_to_path.keys().cloned()))
     }
+
+    async fn trashed(&self) -> Result<Vec<Trashed>> {
+        self.purge_expired_trash()?;
+        Ok(self
+            .read_trash()?
+            .into_iter()
+            .map(|(page, deletion_time, _path)| Trashed {
+                id: page.id().to_owned(),
+                title: page.title,
+                deletion_time,
+            })
+            .collect())
+    }
+
+    async fn restore(&mut self, id: Id) -> Result<page::Parsed> {
+        if self.id_to_path.contains_key(&id) {
+            bail!("Page already exists");
+        }
+        let (page, trashed_path) = self.read_trashed_page_by_id(&id)?;
+        let from_path = trashed_path.with_extension(TRASHED_FROM_EXTENSION);
+
+        // back where it was deleted from, if possible (pages trashed before it was recorded
+        // go to the top directory)
+        let from = std::fs::read_to_string(&from_path)
+            .ok()
+            .map(PathBuf::from)
+            .filter(|from| {
+                from.components()
+                    .all(|component| matches!(component, std::path::Component::Normal(_)))
+            });
+        let dir = from
+            .as_ref()
+            .and_then(|from| from.parent())
+            .unwrap_or_else(|| Path::new(""))
+            .to_owned();
+        let path = match from {
+            Some(from)
+                if !self.path_to_page.contains_key(&from)
+                    && !self.root_path.join(&from).exists() =>
+            {
+                from
+            }
+            _ => self.title_to_new_rel_path(&dir, &page.title, None),
+        };
+        std::fs::create_dir_all(self.root_path.join(&dir))?;
+        rename_no_replace(&trashed_path, &self.root_path.join(&path))?;
+        remove_file_if_exists(&from_path)?;
+        self.id_to_path.insert(id, path.clone());
+        self.path_to_page.insert(path, page.clone());
+        Ok(page)
+    }
+
+    async fn purge(&mut self, id: Id) -> Result<()> {
+        let (_page, trashed_path) = self.read_trashed_page_by_id(&id)?;
+        std::fs::remove_file(&trashed_path)?;
+        remove_file_if_exists(&trashed_path.with_extension(TRASHED_FROM_EXTENSION))?;
+        self.attachments().delete_all(&id)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn test_root_path() -> Result<PathBuf> {
+    let root_path =
+        std::env::temp_dir().join(format!("tagwiki-test-{}", crate::util::random_string(8)));
+    std::fs::create_dir_all(&root_path)?;
+    Ok(root_path)
+}
+
+#[tokio::test]
+async fn rename_on_title_change_test() -> Result<()> {
+    use page::StoreMut;
+
+    let root_path = test_root_path()?;
+    let mut store = FsStore::new(root_path.clone())?.with_rename_on_title_change(true);
+
+    let other = page::Parsed::new("# Bar\n");
+    store.put(&other).await?;
+    let page = page::Parsed::new("# Foo\n");
+    store.put(&page).await?;
+    assert!(root_path.join("foo.md").exists());
+
+    let page = page.with_new_source_body("# FOO\n");
+    store.put(&page).await?;
+    assert!(root_path.join("foo.md").exists());
+
+    let page = page.with_new_source_body("# Bar\n");
+    store.put(&page).await?;
+    assert!(!root_path.join("foo.md").exists());
+    assert!(root_path.join("bar-1.md").exists());
+    assert_eq!(store.get(page.id().to_owned()).await?.title, "Bar");
+
+    // files created behind the store's back are not overwritten
+    std::fs::write(root_path.join("baz.md"), "not a page")?;
+    std::fs::write(root_path.join("qux.md"), "not a page")?;
+    let new_page = page::Parsed::new("# Baz\n");
+    store.put(&new_page).await?;
+    assert!(root_path.join("baz-1.md").exists());
+    let page = page.with_new_source_body("# Qux\n");
+    store.put(&page).await?;
+    assert!(root_path.join("qux-1.md").exists());
+    for name in &["baz.md", "qux.md"] {
+        assert_eq!(std::fs::read_to_string(root_path.join(name))?, "not a page");
+    }
+    assert!(store
+        .write_page_to_file(Path::new("baz"), &new_page, false)
+        .await
+        .is_err());
+    assert_eq!(
+        std::fs::read_to_string(root_path.join("baz.md"))?,
+        "not a page"
+    );
+    assert!(std::fs::read_dir(&root_path)?.all(|entry| !entry
+        .unwrap()
+        .file_name()
+        .to_string_lossy()
+        .contains(".tmp")));
+
+    std::fs::remove_dir_all(&root_path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn trash_and_restore_test() -> Result<()> {
+    use page::StoreMut;
+
+    let root_path = test_root_path()?;
+    let mut store = FsStore::new(root_path.clone())?;
+
+    let page = page::Parsed::new("# Foo\n");
+    store.put(&page).await?;
+    store.delete(page.id().to_owned()).await?;
+    assert!(!root_path.join("foo.md").exists());
+    assert!(store.get(page.id().to_owned()).await.is_err());
+
+    // trashed pages must not be picked up as regular pages
+    let mut store = FsStore::new(root_path.clone())?;
+    assert_eq!(store.iter().await?.count(), 0);
+    let trashed = store.trashed().await?;
+    assert_eq!(trashed.len(), 1);
+    assert_eq!(trashed[0].id, page.id());
+
+    store.restore(page.id().to_owned()).await?;
+    assert!(root_path.join("foo.md").exists());
+    assert!(store.trashed().await?.is_empty());
+
+    // pages in sub-directories, including hidden ones, are restored where they were
+    let nested_path = root_path.join(".notes").join("nested.md");
+    std::fs::create_dir_all(nested_path.parent().unwrap())?;
+    let nested = page::Parsed::new("# Nested\n");
+    std::fs::write(
+        &nested_path,
+        nested.headers.to_markdown_string() + &nested.source_body,
+    )?;
+    let mut store = FsStore::new(root_path.clone())?;
+    store.delete(nested.id().to_owned()).await?;
+    assert!(!nested_path.exists());
+    store.restore(nested.id().to_owned()).await?;
+    assert!(nested_path.exists());
+    assert_eq!(std::fs::read_dir(root_path.join(TRASH_DIR))?.count(), 0);
+    // ... under a new name, if the old one is taken
+    store.delete(nested.id().to_owned()).await?;
+    std::fs::write(&nested_path, "not a page")?;
+    store.restore(nested.id().to_owned()).await?;
+    assert!(root_path.join(".notes").join("nested-1.md").exists());
+
+    store.delete(page.id().to_owned()).await?;
+    store.purge(page.id().to_owned()).await?;
+    assert!(store.trashed().await?.is_empty());
+
+    // IDs come from forms, and must not reach outside of the trash
+    let victim = page::Parsed::new("# Victim\n");
+    store.put(&victim).await?;
+    store.put(&page).await?;
+    store.delete(page.id().to_owned()).await?;
+    assert!(store.purge("../victim".into()).await.is_err());
+    assert!(store.restore("../victim".into()).await.is_err());
+    assert!(root_path.join("victim.md").exists());
+    std::fs::copy(
+        root_path
+            .join(TRASH_DIR)
+            .join(page.id())
+            .with_extension("md"),
+        root_path.join(TRASH_DIR).join("other.md"),
+    )?;
+    assert!(store.purge("other".into()).await.is_err());
+    // ... and the copy with a mismatched ID is not listed
+    assert_eq!(store.trashed().await?.len(), 1);
+
+    std::fs::remove_dir_all(&root_path)?;
+    Ok(())
 }
@@ -2,10 +2,16 @@ use horrorshow::helper::doctype;
 use horrorshow::prelude::*;
 use horrorshow::{box_html, owned_html};
 
+use anyhow::{bail, Result};
 use itertools::Itertools;
+use std::sync::Arc;
 
+use crate::assets;
+use crate::attachment;
+use crate::draft::Draft;
 use crate::index;
-use crate::page::{Parsed, Tag};
+use crate::page::{store::Trashed, Heading, Parsed, Tag};
+use crate::template;
 
 #[derive(Clone, Debug)]
 pub struct PageState {
@@ -13,28 +19,199 @@ pub struct PageState {
     pub path: String,
     pub edit: bool,
     pub page: Option<Parsed>,
+    // the `page` rendered to HTML, in the page view
+    pub rendered: Option<crate::page::Rendered>,
     pub original_page_id: Option<crate::page::Id>,
     pub subtags: Vec<(String, usize)>,
+    // names of files attached to the `page`
+    pub attachments: Vec<String>,
+    // template of the initial content of the editor, when creating a new page
+    pub new_page_template: Option<String>,
+    // templates to choose from, when creating a new page
+    pub templates: Vec<Arc<index::PageInfo>>,
+    // user making the request, if known
+    pub author: Option<String>,
+    // drafts to offer restoring in the editor
+    pub drafts: Vec<Draft>,
+    // draft restored into the editor of a new page
+    pub new_page_draft: Option<Draft>,
+    // title of the new page, when following a dangling `[[Page Title]]` link
+    pub new_page_title: Option<String>,
+    // tags of the requested path no page had, and that were dropped from it
+    pub dropped_tags: Vec<String>,
 }
 
-pub fn html_page(body: impl RenderOnce) -> impl RenderOnce {
-    owned_html! {
-        : doctype::HTML;
-        head {
-            link(rel="stylesheet",href="https://unpkg.com/purecss@2.0.1/build/pure-min.css",crossorigin="anonymous");
-            link(rel="stylesheet",href="https://unpkg.com/purecss@2.0.1/build/grids-responsive-min.css");
-            meta(name="viewport",content="width=device-width, initial-scale=1");
-            link(rel="stylesheet", media="all", href="/_style.css");
+/// IDs of the elements of tagwiki's own UI, that page content must not use
+pub const ELEMENT_IDS: &[&str] = &[
+    "attachment-form",
+    "attachment-input",
+    "attachments",
+    "calendar",
+    "calendar-link",
+    "cancel-button",
+    "delete-button",
+    "edit-button",
+    "feed-link",
+    "index",
+    "list-link",
+    "new-button",
+    "next-month",
+    "next-page",
+    "page-content",
+    "prev-month",
+    "prev-page",
+    "preview",
+    "preview-button",
+    "query-button",
+    "query-text",
+    "recent-button",
+    "recent-changes",
+    "save-button",
+    "source-editor",
+    "tasks-button",
+    "tasks-link",
+    "template-chooser",
+    "today-link",
+    "up-button",
+];
+
+/// Placeholders of the page shell template
+const SHELL_HEAD: &str = "{{head}}";
+const SHELL_BODY: &str = "{{body}}";
+
+/// HTML around the `head` and `body` contents of every page, split at the placeholders
+#[derive(Debug, Clone)]
+struct ShellTemplate {
+    before_head: String,
+    between: String,
+    after_body: String,
+}
+
+/// What every page is wrapped in: the assets, and the page shell
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub assets: assets::Assets,
+    // `None` for the built-in one
+    shell: Option<ShellTemplate>,
+}
+
+impl Layout {
+    pub fn new(assets: assets::Assets) -> Self {
+        Self {
+            assets,
+            shell: None,
         }
-        body {
-           : body;
-           script(src="/_script.js");
+    }
+
+    /// Use a custom `template` of the page shell: a whole HTML document
+    /// with `{{head}}` and `{{body}}` placeholders, in that order
+    pub fn with_shell_template(self, template: &str) -> Result<Self> {
+        Ok(Self {
+            shell: Some(ShellTemplate::parse(template)?),
+            ..self
+        })
+    }
+}
+
+impl ShellTemplate {
+    fn parse(template: &str) -> Result<Self> {
+        let head = match template.find(SHELL_HEAD) {
+            Some(head) => head,
+            None => bail!("Missing {} in the page shell template", SHELL_HEAD),
+        };
+        let body = match template[head..].find(SHELL_BODY) {
+            Some(body) => head + body,
+            None => bail!(
+                "Missing {} (after {}) in the page shell template",
+                SHELL_BODY,
+                SHELL_HEAD
+            ),
+        };
+        Ok(Self {
+            before_head: template[..head].to_owned(),
+            between: template[head + SHELL_HEAD.len()..body].to_owned(),
+            after_body: template[body + SHELL_BODY.len()..].to_owned(),
+        })
+    }
+}
+
+pub fn html_page(layout: &Layout, body: impl RenderOnce) -> impl RenderOnce {
+    let assets = &layout.assets;
+    let (pure_css, grids_css, style_css, highlight_css, custom_css) = (
+        assets.url("pure-min.css"),
+        assets.url("grids-responsive-min.css"),
+        assets.url("style.css"),
+        assets.url("highlight.css"),
+        assets
+            .get(assets::CUSTOM_CSS)
+            .map(|_| assets.url(assets::CUSTOM_CSS)),
+    );
+    let (script_js, katex_js, custom_js) = (
+        assets.url("script.js"),
+        assets.url("katex.min.js"),
+        assets
+            .get(assets::CUSTOM_JS)
+            .map(|_| assets.url(assets::CUSTOM_JS)),
+    );
+    let head = owned_html! {
+        meta(name="viewport",content="width=device-width, initial-scale=1");
+        link(rel="stylesheet", media="all", href=pure_css);
+        link(rel="stylesheet", media="all", href=grids_css);
+        link(rel="stylesheet", media="all", href=style_css);
+        link(rel="stylesheet", media="all", href=highlight_css);
+        @ if let Some(custom_css) = custom_css {
+            link(rel="stylesheet", media="all", href=custom_css);
+        }
+    };
+    let body = owned_html! {
+        : body;
+        script(src=script_js, data-katex-url=katex_js);
+        @ if let Some(custom_js) = custom_js {
+            script(src=custom_js);
+        }
+    };
+    let shell = layout.shell.clone();
+    owned_html! {
+        @ if let Some(shell) = shell {
+            : Raw(shell.before_head);
+            : head;
+            : Raw(shell.between);
+            : body;
+            : Raw(shell.after_body);
+        } else {
+            : doctype::HTML;
+            head {
+                : head;
+            }
+            body {
+                : body;
+            }
         }
     }
 }
 
+#[test]
+fn shell_template_test() {
+    let layout = Layout::new(assets::Assets::builtin());
+    assert!(layout
+        .clone()
+        .with_shell_template("<html>{{body}}{{head}}</html>")
+        .is_err());
+    let layout = layout
+        .with_shell_template(
+            "<html><head>{{head}}</head><body><nav>Ours</nav>{{body}}</body></html>",
+        )
+        .unwrap();
+    let html = html_page(&layout, owned_html! { p : "content" })
+        .into_string()
+        .unwrap();
+    assert!(html.starts_with("<html><head><meta"));
+    assert!(html.contains("</head><body><nav>Ours</nav><p>content</p><script"));
+    assert!(html.ends_with("</body></html>"));
+}
+
 pub fn page(page_state: PageState) -> Box<dyn RenderBox> {
-    if page_state.edit.clone() {
+    if page_state.edit {
         Box::new(page_editing_view(page_state.clone())) as Box<dyn RenderBox>
     } else {
         let page_state_clone = page_state.clone();
@@ -54,50 +231,189 @@ pub fn page(page_state: PageState) -> Box<dyn RenderBox> {
     }
 }
 
+/// Notices about unsaved drafts, with buttons to restore or discard them
+fn draft_notices(drafts: Vec<Draft>, page_id: Option<String>) -> impl RenderOnce {
+    let id_param = page_id.map(|id| format!("id={}&", id)).unwrap_or_default();
+    owned_html! {
+        @ for draft in &drafts {
+            div(class="draft-notice") {
+                : format!(
+                    "Unsaved draft from {}: \"{}\" ",
+                    draft.time.format("%Y-%m-%d %H:%M"),
+                    draft.summary()
+                );
+                a(href=format!("?{}edit=true&draft={}", id_param, draft.key), class="pure-button") {
+                    : "Restore"
+                }
+                : " ";
+                button(type="submit", class="pure-button", formaction=format!("/_drafts/{}", draft.key), formmethod="post", name="_method", value="delete") {
+                    : "Discard"
+                }
+            }
+        }
+    }
+}
+
 pub fn page_editing_view(page_state: PageState) -> impl RenderOnce {
+    let drafts = page_state.drafts.clone();
     if let Some(page) = page_state.page.as_ref() {
         let body = page.source_body.clone();
+        let id = page.id().to_owned();
+        let draft_notices = draft_notices(drafts, Some(id.clone()));
         menu(
             page_state.clone(),
             Some(
                 (box_html! {
-                    textarea(name="body", id="source-editor", class="append", autofocus) {
+                    : draft_notices;
+                    textarea(name="body", id="source-editor", class="append", autofocus, data-page-id=&id) {
                         : body
                     }
+                    : attachment_input();
+                    article(id="preview", hidden) {}
                 }) as Box<dyn RenderBox>,
             ),
         )
     } else {
         let starting_tags = page_state
             .path
-            .split("/")
+            .split('/')
             .filter(|t| !t.trim().is_empty())
-            .map(|t| format!("#{}", t))
-            .collect::<Vec<_>>()
-            .join(" ");
-        let starting_text = "\n\n\n".to_string() + &starting_tags;
+            .map(crate::util::decode_path_segment)
+            .collect::<Vec<_>>();
+        let starting_tags: Vec<_> = starting_tags.iter().map(String::as_str).collect();
+        let starting_text = template::expand(
+            page_state
+                .new_page_template
+                .as_deref()
+                .unwrap_or(template::DEFAULT_NEW_PAGE),
+            &template::Context {
+                time: crate::util::now(),
+                tags: &starting_tags,
+                user: page_state.author.as_deref(),
+            },
+        );
+        let templates = page_state.templates.clone();
+        // keep the title when switching templates
+        let title_param = page_state
+            .new_page_title
+            .as_deref()
+            .map(|title| {
+                format!(
+                    "&title={}",
+                    percent_encoding::utf8_percent_encode(
+                        title,
+                        percent_encoding::NON_ALPHANUMERIC
+                    )
+                )
+            })
+            .unwrap_or_default();
+        // pick the ID upfront, so files can be attached before the first save
+        // (and to save drafts under)
+        let (new_id, starting_text) = if let Some(draft) = page_state.new_page_draft.clone() {
+            (draft.key, draft.body)
+        } else {
+            (crate::util::random_string(16), starting_text)
+        };
+        let draft_notices = draft_notices(drafts, None);
         menu(
             page_state.clone(),
             Some(
                 (box_html! {
-                    textarea(name="body", id="source-editor", class="prepend", autofocus) {
+                    : draft_notices;
+                    @ if !templates.is_empty() {
+                        div(id="template-chooser") {
+                            : "Template: ";
+                            a(href=format!("?edit=true&template={}", title_param)) { : "none" }
+                            @ for template in &templates {
+                                : " ";
+                                a(href=format!("?edit=true&template={}{}", template.id, title_param)) { : &template.title }
+                            }
+                        }
+                    }
+                    input(type="hidden", name="new_id", value=&new_id);
+                    textarea(name="body", id="source-editor", class="prepend", autofocus, data-page-id=&new_id) {
                         : starting_text
                     }
+                    : attachment_input();
+                    article(id="preview", hidden) {}
                 }) as Box<dyn RenderBox>,
             ),
         )
     }
 }
 
+/// File picker uploading attachments and inserting links to them into the editor (requires JS)
+fn attachment_input() -> impl RenderOnce {
+    owned_html! {
+        label(for="attachment-input") { : "Attach files (or paste/drop them into the editor): " }
+        input(type="file", id="attachment-input", multiple);
+    }
+}
+
+/// Tags of the current path, each linking to the path up to it, and removable
+fn breadcrumbs(path: &str, dropped_tags: Vec<String>) -> impl RenderOnce {
+    let segments: Vec<_> = path
+        .split('/')
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| s.to_owned())
+        .collect();
+    let href = |segments: Vec<&String>| {
+        if segments.is_empty() {
+            "/".to_owned()
+        } else {
+            format!("/{}/", segments.into_iter().join("/"))
+        }
+    };
+    let crumbs: Vec<_> = (0..segments.len())
+        .map(|i| {
+            (
+                crate::util::decode_path_segment(&segments[i]),
+                href(segments[..=i].iter().collect()),
+                // the path without this tag
+                href(
+                    segments
+                        .iter()
+                        .enumerate()
+                        .filter(|&(j, _)| j != i)
+                        .map(|(_, segment)| segment)
+                        .collect(),
+                ),
+            )
+        })
+        .collect();
+    owned_html! {
+        nav(class="breadcrumbs") {
+            a(href="/") : "All";
+            @ for (tag, href, without_href) in &crumbs {
+                : " / ";
+                a(href=href) : tag;
+                : " ";
+                a(href=without_href, class="breadcrumb-remove", title=format!("Remove {}", tag)) : "×";
+            }
+        }
+        @ if !dropped_tags.is_empty() {
+            div(class="dropped-notice") {
+                : format!(
+                    "No pages have all the requested tags, so {} {} left out.",
+                    dropped_tags.iter().map(|tag| format!("\"{}\"", tag)).join(", "),
+                    if dropped_tags.len() == 1 { "was" } else { "were" }
+                );
+            }
+        }
+    }
+}
+
 pub fn menu(page_state: PageState, subform: Option<Box<dyn RenderBox>>) -> impl RenderOnce {
+    let breadcrumbs = breadcrumbs(&page_state.path, page_state.dropped_tags.clone());
     let id = page_state.page.map(|p| p.id().to_owned());
     let edit = page_state.edit;
     let original_page_id = page_state.original_page_id;
     let path_tags: String = page_state
         .path
-        .split("/")
+        .split('/')
         .filter(|f| !f.trim().is_empty())
-        .collect::<Vec<&str>>()
+        .map(crate::util::decode_path_segment)
+        .collect::<Vec<_>>()
         .join(" ");
 
     // # The sucky menu mega-form
@@ -131,6 +447,11 @@ pub fn menu(page_state: PageState, subform: Option<Box<dyn RenderBox>>) -> impl
                     : " ";
                 }
                 @ if edit {
+                    // shown by the script, as it doesn't work without it
+                    button(type="button", id="preview-button", class="pure-button", hidden) {
+                        : Raw("<u>P</u>review")
+                    }
+                    : " ";
                     @ if let Some(_id) = id.as_deref() {
                         button(type="submit", id="save-button", class="pure-button pure-button-primary", formaction=".", formmethod="post"){
                             : Raw("<u>S</u>ave")
@@ -160,20 +481,136 @@ pub fn menu(page_state: PageState, subform: Option<Box<dyn RenderBox>>) -> impl
                 }
                 input(type="text", class="float-right", id="query-text", name="q", placeholder="tag1 tag2...", value=path_tags);
             }
+            : breadcrumbs;
             : subform
         }
     }
 }
 
+/// Minimum number of sections (headings other than the title) of pages with a table of contents
+const TOC_MIN_SECTIONS: usize = 3;
+
+/// Table of contents of a page, if it's long enough to need one
+fn toc(headings: &[Heading]) -> impl RenderOnce {
+    // the title is not a section
+    let sections = match headings {
+        [title, sections @ ..] if title.level == 1 => sections,
+        sections => sections,
+    }
+    .to_vec();
+    owned_html! {
+        @ if TOC_MIN_SECTIONS <= sections.len() {
+            nav(class="toc") {
+                details(open) {
+                    summary : "Contents";
+                    ul {
+                        @ for heading in &sections {
+                            li(class=format!("toc-level-{}", heading.level)) {
+                                a(href=format!("#{}", heading.id)) : &heading.text
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 pub fn page_view(page_state: PageState, sub_pages: impl RenderOnce) -> impl RenderOnce {
     let menu = menu(page_state.clone(), None);
     let page = page_state.page.expect("always some");
-    let page_html = page.html.clone();
+    let rendered = page_state.rendered.unwrap_or_default();
+    let toc = toc(&rendered.headings);
+    let page_html = rendered.html;
+    let id = page.id().to_owned();
+    let attachments = page_state.attachments;
     owned_html! {
         : menu;
         article(id="page-content") {
+            : toc;
             : Raw(page_html);
             : sub_pages;
+            @ if !attachments.is_empty() {
+                h2 { : "Attachments" }
+                ul(id="attachments") {
+                    @ for name in &attachments {
+                        li {
+                            a(href=attachment::url(&id, name)) : name
+                        }
+                    }
+                }
+            }
+            form(class="pure-form", id="attachment-form", action=format!("/_attachments/{}/", id), method="post", enctype="multipart/form-data") {
+                input(type="file", name="file", multiple);
+                : " ";
+                button(type="submit", class="pure-button") { : "Attach" }
+            }
+        }
+    }
+}
+
+/// Number of pages displayed at once in a page listing
+pub const LISTING_PAGE_SIZE: usize = 100;
+
+/// Which part of a page listing to display, and in what order
+#[derive(Clone, Copy, Debug)]
+pub struct Listing {
+    pub sort: index::SortOrder,
+    // 1-based
+    pub page: usize,
+    pub page_count: usize,
+}
+
+impl Listing {
+    pub fn new(sort: index::SortOrder, page: Option<usize>, total: usize) -> Self {
+        let page_count = std::cmp::max(1, total.div_ceil(LISTING_PAGE_SIZE));
+        Self {
+            sort,
+            page: page.unwrap_or(1).max(1).min(page_count),
+            page_count,
+        }
+    }
+
+    /// Number of pages before the displayed ones
+    pub fn skip(&self) -> usize {
+        (self.page - 1) * LISTING_PAGE_SIZE
+    }
+
+    fn href(&self, sort: index::SortOrder, page: usize) -> String {
+        format!("?sort={}&page={}", sort.as_str(), page)
+    }
+}
+
+fn listing_nav(listing: Listing) -> impl RenderOnce {
+    owned_html! {
+        div(class="listing-nav") {
+            : "Sort by: ";
+            @ for sort in index::SortOrder::ALL.iter().copied() {
+                @ if sort == listing.sort {
+                    b { : sort.as_str() }
+                } else {
+                    a(href=listing.href(sort, 1)) { : sort.as_str() }
+                }
+                : " ";
+            }
+            : " | ";
+            a(href="?calendar=", id="calendar-link") { : "Calendar" }
+            : " ";
+            a(href="_feed.atom", id="feed-link") { : "Feed" }
+            : " ";
+            a(href="_tasks", id="tasks-link") { : "Tasks" }
+            @ if listing.page_count > 1 {
+                : " | ";
+                @ if listing.page > 1 {
+                    a(href=listing.href(listing.sort, listing.page - 1), id="prev-page") { : "« Previous" }
+                    : " ";
+                }
+                : format!("Page {} of {}", listing.page, listing.page_count);
+                @ if listing.page < listing.page_count {
+                    : " ";
+                    a(href=listing.href(listing.sort, listing.page + 1), id="next-page") { : "Next »" }
+                }
+            }
         }
     }
 }
@@ -181,23 +618,44 @@ pub fn page_view(page_state: PageState, sub_pages: impl RenderOnce) -> impl Rend
 pub fn post_list(
     page_state: PageState,
     unmatched_tags: Vec<(Tag, usize)>,
-    posts: impl Iterator<Item = index::PageInfo> + 'static,
+    posts: impl Iterator<Item = Arc<index::PageInfo>> + 'static,
+    listing: Listing,
 ) -> impl RenderOnce {
     let menu = menu(page_state.clone(), None);
+    // group by the date the pages are sorted by, if any
+    let groups: Vec<(Option<String>, Vec<Arc<index::PageInfo>>)> = match listing.sort {
+        index::SortOrder::Created | index::SortOrder::Modified => posts
+            .group_by(move |info| {
+                if listing.sort == index::SortOrder::Created {
+                    info.headers.creation_time.date()
+                } else {
+                    info.headers.modification_time.date()
+                }
+            })
+            .map(|(date, group)| (Some(date.format("%A, %Y-%m-%d").to_string()), group))
+            .collect(),
+        index::SortOrder::Title | index::SortOrder::Relevance => vec![(None, posts.collect())],
+    };
     owned_html! {
         : menu;
         div(id="page-content") {
             h1 { : "Pages" }
-            @ for (date, group) in posts.group_by(|info| info.headers.creation_time.date()) {
-                h3 { : date.format("%A, %Y-%m-%d").to_string() }
+            : listing_nav(listing);
+            @ for (date, group) in groups {
+                @ if let Some(date) = date {
+                    h3 { : date }
+                }
                 ul {
                     @ for post in group {
                         li {
-                            a(href=format!("./?id={}", post.id)) : post.title
+                            a(href=format!("./?id={}", post.id)) : &post.title
                         }
                     }
                 }
             }
+            @ if listing.page_count > 1 {
+                : listing_nav(listing);
+            }
             @ if !unmatched_tags.is_empty() {
                 h1 { : "Subtags" }
                 ul(id="index") {
@@ -211,3 +669,306 @@ pub fn post_list(
         }
     }
 }
+
+/// Pages of a month, for the calendar view
+pub struct CalendarMonth {
+    /// First day of the month
+    pub month: chrono::NaiveDate,
+    /// Pages, by the day of the month they were created on
+    pub pages_by_day: std::collections::BTreeMap<u32, Vec<Arc<index::PageInfo>>>,
+}
+
+fn month_href(month: chrono::NaiveDate) -> String {
+    format!("?calendar={}", month.format("%Y-%m"))
+}
+
+/// First days of the months before and after `month` (the first day of one)
+fn adjacent_months(month: chrono::NaiveDate) -> (chrono::NaiveDate, chrono::NaiveDate) {
+    use chrono::Datelike;
+
+    (
+        (month - chrono::Duration::days(1))
+            .with_day(1)
+            .expect("valid day"),
+        (month + chrono::Duration::days(31))
+            .with_day(1)
+            .expect("valid day"),
+    )
+}
+
+/// Days of `month` (the first day of one), in full weeks starting on Monday;
+/// `None` pads the days outside of the month
+fn month_days(month: chrono::NaiveDate) -> Vec<Option<u32>> {
+    use chrono::Datelike;
+
+    let (_, next_month) = adjacent_months(month);
+    let days_in_month = (next_month - month).num_days() as u32;
+    let mut days: Vec<Option<u32>> = vec![None; month.weekday().num_days_from_monday() as usize];
+    days.extend((1..=days_in_month).map(Some));
+    while !days.len().is_multiple_of(7) {
+        days.push(None);
+    }
+    days
+}
+
+pub fn calendar(page_state: PageState, calendar: CalendarMonth) -> impl RenderOnce {
+    use chrono::Datelike;
+
+    let menu = menu(page_state, None);
+    let month = calendar.month;
+    let mut pages_by_day = calendar.pages_by_day;
+    let (prev_month, next_month) = adjacent_months(month);
+    let today = crate::util::today();
+
+    let weeks: Vec<Vec<_>> = month_days(month)
+        .chunks(7)
+        .map(|week| {
+            week.iter()
+                .map(|day| {
+                    (
+                        *day,
+                        day.and_then(|day| pages_by_day.remove(&day))
+                            .unwrap_or_default(),
+                    )
+                })
+                .collect()
+        })
+        .collect();
+
+    owned_html! {
+        : menu;
+        div(id="page-content") {
+            h1 { : month.format("%B %Y").to_string() }
+            div(class="listing-nav") {
+                a(href=month_href(prev_month), id="prev-month") { : "« Previous" }
+                : " ";
+                a(href=month_href(today.with_day(1).expect("valid day"))) { : "This month" }
+                : " ";
+                a(href=month_href(next_month), id="next-month") { : "Next »" }
+                : " | ";
+                a(href="?today=true", id="today-link") { : "Today's entry" }
+                : " ";
+                a(href=".", id="list-link") { : "List" }
+            }
+            table(class="pure-table pure-table-bordered", id="calendar") {
+                thead {
+                    tr {
+                        @ for name in ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"].iter() {
+                            th { : *name }
+                        }
+                    }
+                }
+                tbody {
+                    @ for week in weeks {
+                        tr {
+                            @ for (day, pages) in week {
+                                @ if let Some(day) = day {
+                                    td(class=if month.with_day(day) == Some(today) { "calendar-today" } else { "" }) {
+                                        div(class="calendar-day") { : day }
+                                        @ for page in pages {
+                                            div {
+                                                a(href=format!("?id={}", page.id)) { : &page.title }
+                                            }
+                                        }
+                                    }
+                                } else {
+                                    td {}
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Menu of the special (`/_...`) views, that are not tied to any tag path
+pub fn special_menu() -> impl RenderOnce {
+    owned_html! {
+        form(class="pure-form") {
+            div(class="pure-menu pure-menu-horizontal") {
+                a(href="/", class="pure-button", id="up-button") { : "Up" }
+                : " ";
+                a(href="/_recent/", class="pure-button", id="recent-button") { : "Recent" }
+                : " ";
+                a(href="/_tasks", class="pure-button", id="tasks-button") { : "Tasks" }
+                : " ";
+                button(type="submit", id="query-button", class="pure-button float-right", formaction="/_query", formmethod="get") {
+                    : "Search"
+                }
+                input(type="text", class="float-right", id="query-text", name="q", placeholder="tag1 tag2...");
+            }
+        }
+    }
+}
+
+pub fn recent(pages: Vec<Arc<index::PageInfo>>) -> impl RenderOnce {
+    owned_html! {
+        : special_menu();
+        div(id="page-content") {
+            h1 { : "Recent changes" }
+            table(class="pure-table pure-table-horizontal", id="recent-changes") {
+                thead {
+                    tr {
+                        th { : "Time" }
+                        th { : "Page" }
+                        th { : "By" }
+                        th { : "Change" }
+                    }
+                }
+                tbody {
+                    @ for page in pages {
+                        tr {
+                            td { : page.headers.modification_time.format("%Y-%m-%d %H:%M").to_string() }
+                            td {
+                                a(href=format!("/?id={}", page.id)) { : &page.title }
+                            }
+                            td { : page.headers.modified_by.as_deref().unwrap_or("") }
+                            td { : page.headers.change_summary.as_deref().unwrap_or("") }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Open tasks of pages matching `tags`
+pub fn tasks(
+    tags: Vec<Tag>,
+    tasks_by_page: Vec<(Arc<index::PageInfo>, Vec<crate::page::Task>)>,
+) -> impl RenderOnce {
+    owned_html! {
+        : special_menu();
+        div(id="page-content") {
+            h1 {
+                : "Open tasks";
+                @ if !tags.is_empty() {
+                    : format!(" of {}", tags.iter().map(|tag| format!("#{}", tag)).collect::<Vec<_>>().join(" "));
+                }
+            }
+            @ if tasks_by_page.is_empty() {
+                p { : "Nothing to do." }
+            }
+            @ for (page, tasks) in tasks_by_page {
+                h2 {
+                    a(href=format!("/?id={}", page.id)) { : &page.title }
+                }
+                ul(class="task-list") {
+                    @ for task in tasks {
+                        li {
+                            // same as checkboxes of a rendered page
+                            input(type="checkbox", class="task-checkbox", data-page-id=&page.id, data-task=task.index, data-task-text=&task.text, disabled);
+                            : " ";
+                            : &task.text
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn trash(trashed: Vec<(Trashed, usize)>) -> impl RenderOnce {
+    owned_html! {
+        : special_menu();
+        div(id="page-content") {
+            h1 { : "Trash" }
+            @ if trashed.is_empty() {
+                p { : "Trash is empty." }
+            }
+            ul {
+                @ for (page, attachment_count) in trashed {
+                    li {
+                        form(class="pure-form", action="/_trash/", method="post") {
+                            input(type="hidden", name="id", value=&page.id);
+                            : format!("{} (deleted {}) ", page.title, page.deletion_time.format("%Y-%m-%d %H:%M"));
+                            @ if attachment_count > 0 {
+                                : format!("[{} attachment(s)] ", attachment_count);
+                            }
+                            button(type="submit", class="pure-button", name="_method", value="restore") {
+                                : "Restore"
+                            }
+                            : " ";
+                            button(type="submit", class="pure-button button-warning", name="_method", value="delete", onclick="return confirm('Delete permanently?');") {
+                                : "Delete permanently"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn calendar_month_test() {
+    let date = |y, m, d| chrono::NaiveDate::from_ymd_opt(y, m, d).unwrap();
+
+    assert_eq!(
+        adjacent_months(date(2026, 1, 1)),
+        (date(2025, 12, 1), date(2026, 2, 1))
+    );
+    assert_eq!(
+        adjacent_months(date(2026, 12, 1)),
+        (date(2026, 11, 1), date(2027, 1, 1))
+    );
+    assert_eq!(
+        adjacent_months(date(2026, 3, 1)),
+        (date(2026, 2, 1), date(2026, 4, 1))
+    );
+
+    // starts on a Sunday, 28 days
+    let days = month_days(date(2026, 2, 1));
+    assert_eq!(days.len(), 35);
+    assert_eq!(&days[..7], &[None, None, None, None, None, None, Some(1)]);
+    assert_eq!(days[33], Some(28));
+    assert_eq!(days[34], None);
+
+    // leap year, starts on a Thursday
+    let days = month_days(date(2024, 2, 1));
+    assert_eq!(days.len(), 35);
+    assert_eq!(&days[..4], &[None, None, None, Some(1)]);
+    assert_eq!(days[31], Some(29));
+    assert_eq!(days[32], None);
+
+    // starts on a Monday, 30 days
+    let days = month_days(date(2026, 6, 1));
+    assert_eq!(days.len(), 35);
+    assert_eq!(days[0], Some(1));
+    assert_eq!(days[29], Some(30));
+    assert!(days[30..].iter().all(Option::is_none));
+
+    // 31 days, starting on a Saturday, need 6 weeks
+    let days = month_days(date(2026, 8, 1));
+    assert_eq!(days.len(), 42);
+    assert_eq!(days[5], Some(1));
+    assert_eq!(days[35], Some(31));
+}
+
+#[test]
+fn breadcrumbs_test() {
+    let html = breadcrumbs("/work/status%3Aopen/due%3C2026-11-01/", vec![])
+        .into_string()
+        .unwrap();
+    assert!(html.starts_with(r#"<nav class="breadcrumbs"><a href="/">All</a> / <a href="/work/">work</a> <a href="/status%3Aopen/due%3C2026-11-01/" class="breadcrumb-remove" title="Remove work">×</a>"#));
+    assert!(html.contains(r#" / <a href="/work/status%3Aopen/">status:open</a> <a href="/work/due%3C2026-11-01/" class="breadcrumb-remove" title="Remove status:open">×</a>"#));
+    assert!(html.contains(r#" / <a href="/work/status%3Aopen/due%3C2026-11-01/">due&lt;2026-11-01</a> <a href="/work/status%3Aopen/" class="breadcrumb-remove" title="Remove due&lt;2026-11-01">×</a></nav>"#));
+    assert!(!html.contains("dropped-notice"));
+
+    // removing the only tag leads to all pages
+    let html = breadcrumbs("/journal/", vec!["work".into(), "x".into()])
+        .into_string()
+        .unwrap();
+    assert!(html.contains(r#"<a href="/" class="breadcrumb-remove" title="Remove journal">×</a>"#));
+    assert!(html.contains(
+        r#"No pages have all the requested tags, so &quot;work&quot;, &quot;x&quot; were left out."#
+    ));
+
+    let html = breadcrumbs("/", vec![]).into_string().unwrap();
+    assert_eq!(
+        html,
+        r#"<nav class="breadcrumbs"><a href="/">All</a></nav>"#
+    );
+}
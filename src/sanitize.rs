@@ -0,0 +1,193 @@
+//! Sanitization of page HTML
+//!
+//! Markdown lets pages include any raw HTML, so without sanitization anyone able
+//! to edit pages could run scripts in the browsers of everyone reading them.
+//!
+//! HTML generated by tagwiki itself (task checkboxes, wiki links, highlighted code etc.)
+//! is not sanitized, but kept aside (see `Generated`), so pages can't fake it.
+
+use pulldown_cmark::{CowStr, Event, Tag};
+use std::collections::HashMap;
+
+/// Start of the HTML written in a page, in markdown events (see `Generated::mark_page_html`)
+const PAGE_HTML_MARK: char = '\0';
+
+/// HTML allowed in pages, on top of the ammonia defaults and what tagwiki itself generates
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub extra_tags: Vec<String>,
+    // allowed on all tags
+    pub extra_attributes: Vec<String>,
+}
+
+/// Remove anything not allowed by `config` from `html`
+pub fn sanitize(html: &str, config: &Config) -> String {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .link_rel(None)
+        .add_tags(config.extra_tags.iter().map(String::as_str))
+        .add_generic_attributes(config.extra_attributes.iter().map(String::as_str));
+    // math is marked up before parsing markdown (see `math`), so comes as page HTML;
+    // allowing any classes (ammonia doesn't allow both) makes it unnecessary
+    if !config.extra_attributes.iter().any(|name| name == "class") {
+        builder.add_allowed_classes("span", &["math", "math-inline", "math-display"]);
+    }
+    builder.clean(html).to_string()
+}
+
+/// HTML generated while rendering a page, replaced by placeholders for sanitization
+///
+/// Placeholders contain a random nonce, so pages can't include them.
+pub struct Generated {
+    nonce: String,
+    fragments: Vec<String>,
+    // numbers of footnotes, by their names
+    footnotes: HashMap<String, usize>,
+}
+
+impl Generated {
+    pub fn new() -> Self {
+        Self {
+            nonce: crate::util::random_string(16),
+            fragments: vec![],
+            footnotes: HashMap::new(),
+        }
+    }
+
+    /// Mark HTML written in the page, for `extract` to tell it apart from the generated one
+    ///
+    /// Meant for the events straight out of the markdown parser.
+    pub fn mark_page_html(event: Event) -> Event {
+        match event {
+            Event::Html(html) => Event::Html(format!("{}{}", PAGE_HTML_MARK, html).into()),
+            event => event,
+        }
+    }
+
+    fn placeholder<'a>(&mut self, html: String) -> Event<'a> {
+        self.fragments.push(html);
+        Event::Html(format!("tagwiki{}x{}x", self.nonce, self.fragments.len() - 1).into())
+    }
+
+    fn footnote_number(&mut self, name: &str) -> usize {
+        let next = self.footnotes.len() + 1;
+        *self.footnotes.entry(name.to_owned()).or_insert(next)
+    }
+
+    /// Replace the generated HTML in `events` with placeholders
+    ///
+    /// Footnotes are generated here too, with IDs that can't clash with the ones
+    /// of tagwiki's own elements.
+    pub fn extract<'a>(&mut self, events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+        use crate::util::escape_xml;
+
+        events
+            .map(|event| match event {
+                Event::Html(html) => match html.strip_prefix(PAGE_HTML_MARK) {
+                    Some(page_html) => Event::Html(CowStr::from(page_html.to_owned())),
+                    None => self.placeholder(html.into_string()),
+                },
+                Event::FootnoteReference(name) => {
+                    let html = format!(
+                        r##"<sup class="footnote-reference"><a href="#footnote-{}">{}</a></sup>"##,
+                        escape_xml(&name),
+                        self.footnote_number(&name)
+                    );
+                    self.placeholder(html)
+                }
+                Event::Start(Tag::FootnoteDefinition(name)) => {
+                    let html = format!(
+                        r#"<div class="footnote-definition" id="footnote-{}"><sup class="footnote-definition-label">{}</sup>"#,
+                        escape_xml(&name),
+                        self.footnote_number(&name)
+                    );
+                    self.placeholder(html)
+                }
+                Event::End(Tag::FootnoteDefinition(_)) => self.placeholder("</div>\n".into()),
+                event => event,
+            })
+            .collect()
+    }
+
+    /// Replace placeholders in (sanitized) `html` with the generated HTML
+    pub fn insert(&self, html: &str) -> String {
+        if self.fragments.is_empty() {
+            return html.to_owned();
+        }
+        regex::Regex::new(&format!(r"tagwiki{}x(\d+)x", self.nonce))
+            .expect("correct regex")
+            .replace_all(html, |cap: &regex::Captures| {
+                cap[1]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| self.fragments.get(index))
+                    .cloned()
+                    .unwrap_or_default()
+            })
+            .into_owned()
+    }
+}
+
+#[test]
+fn sanitize_test() {
+    let page = crate::page::Parsed::new_with_id(
+        "abc".into(),
+        "# Title\n\n<script>alert(1)</script>\n\n<a href=\"javascript:alert(1)\" onclick=\"alert(1)\">x</a> [[Link]]\n\n- [ ] task\n",
+    )
+    .render(&crate::page::RenderConfig::default());
+    assert!(!page.html.contains("script"));
+    assert!(!page.html.contains("onclick"));
+    assert!(page.html.contains("<a>x</a>"));
+    assert!(page
+        .html
+        .contains(r#"<a class="wiki-link" data-target="Link" href="/_link?to=Link">Link</a>"#));
+    assert!(page.html.contains(
+        r#"<input type="checkbox" class="task-checkbox" data-page-id="abc" data-task="0" data-task-text="task" disabled>"#
+    ));
+}
+
+#[test]
+fn generated_html_test() {
+    // tagwiki's own markup can't be faked
+    let page = crate::page::Parsed::new_with_id(
+        "abc".into(),
+        "# Source editor\n\n<input type=\"checkbox\" class=\"task-checkbox\" data-page-id=\"other\" data-task=\"0\">\n\n\
+         <div class=\"wiki-embed\" data-target=\"x\"><a class=\"wiki-link\" data-target=\"x\" href=\"/\">x</a></div>\n\n\
+         <div class=\"diagram diagram-dot\" data-diagram=\"0\"></div>\n\n\
+         <p id=\"preview\">p</p> <span class=\"math math-inline other\">x</span>\n\n\
+         Note[^source-editor].\n\n[^source-editor]: <b>Footnote</b>\n",
+    )
+    .render(&crate::page::RenderConfig::default());
+    assert!(!page.html.contains("<input"));
+    assert!(!page.html.contains("data-"));
+    assert!(!page.html.contains("class=\"wiki-"));
+    assert!(!page.html.contains("class=\"diagram"));
+    assert!(!page.html.contains("id=\"preview\""));
+    assert!(!page.html.contains("id=\"source-editor\""));
+    assert!(page
+        .html
+        .contains(r#"<span class="math math-inline">x</span>"#));
+    assert!(page
+        .html
+        .contains(r#"<h1 id="source-editor-1">Source editor</h1>"#));
+    assert!(page.html.contains(
+        r##"<sup class="footnote-reference"><a href="#footnote-source-editor">1</a></sup>"##
+    ));
+    assert!(page.html.contains(
+        r#"<div class="footnote-definition" id="footnote-source-editor"><sup class="footnote-definition-label">1</sup>"#
+    ));
+    assert!(page.html.contains("<b>Footnote</b>"));
+
+    // nor can its placeholders
+    let mut generated = Generated::new();
+    let events = generated.extract(
+        vec![
+            Event::Html("<b>generated</b>".into()),
+            Generated::mark_page_html(Event::Html("tagwikixxxx0x".into())),
+        ]
+        .into_iter(),
+    );
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+    assert_eq!(generated.insert(&html), "<b>generated</b>tagwikixxxx0x");
+}
@@ -0,0 +1,99 @@
+//! Templates of new pages
+//!
+//! Besides the built-in ones, any page tagged `#template` is a template.
+//! It is applied automatically to new pages created under a path with
+//! all its other tags (eg. a `#template #meeting` page for new pages in `/meeting/`).
+
+use chrono::{DateTime, FixedOffset};
+use lazy_static::lazy_static;
+
+/// Tag marking template pages
+pub const TEMPLATE_TAG: &str = "template";
+
+/// Template of new pages, if no other applies
+pub const DEFAULT_NEW_PAGE: &str = "\n\n\n{{tags}}";
+
+/// Template of daily journal pages, unless configured otherwise
+pub const DEFAULT_DAILY_NOTE: &str = "# {{date}}\n\n\n{{tags}}\n";
+
+/// Values of placeholders
+pub struct Context<'a> {
+    pub time: DateTime<FixedOffset>,
+    pub tags: &'a [&'a str],
+    pub user: Option<&'a str>,
+}
+
+/// Expand placeholders of a `template`
+///
+/// Supported placeholders:
+///
+/// * `{{date}}` - eg. `2026-10-19`
+/// * `{{time}}` - eg. `14:05`
+/// * `{{weekday}}` - eg. `Monday`
+/// * `{{tags}}` - current tags, as `#tag1 #tag2`
+/// * `{{user}}` - user creating the page, if known
+pub fn expand(template: &str, context: &Context) -> String {
+    let tags = context
+        .tags
+        .iter()
+        .map(|tag| format!("#{}", tag))
+        .collect::<Vec<_>>()
+        .join(" ");
+    template
+        .replace("{{date}}", &context.time.format("%Y-%m-%d").to_string())
+        .replace("{{time}}", &context.time.format("%H:%M").to_string())
+        .replace("{{weekday}}", &context.time.format("%A").to_string())
+        .replace("{{tags}}", &tags)
+        .replace("{{user}}", context.user.unwrap_or(""))
+}
+
+/// Make a `template` start with a given `title` heading, replacing its own, if any
+///
+/// Used when creating a page by following a `[[Page Title]]` link to it.
+pub fn with_title(template: &str, title: &str) -> String {
+    let body = match template.split_once('\n') {
+        Some((first, rest)) if first.starts_with("# ") => rest,
+        None if template.starts_with("# ") => "",
+        _ => template,
+    };
+    format!("# {}\n{}", title, body)
+}
+
+/// Turn the body of a template page into a template, by removing the tag marking it as one
+pub fn from_page_body(body: &str) -> String {
+    lazy_static! {
+        static ref RE: regex::Regex =
+            regex::Regex::new(r"(?i)[ \t]?#template\b").expect("correct regex");
+    }
+    RE.replace_all(body, "").into_owned()
+}
+
+#[test]
+fn expand_test() {
+    let time = DateTime::parse_from_rfc3339("2026-10-19T14:05:00+02:00").unwrap();
+    let context = Context {
+        time,
+        tags: &["journal", "work"],
+        user: Some("alice"),
+    };
+    assert_eq!(
+        expand(DEFAULT_DAILY_NOTE, &context),
+        "# 2026-10-19\n\n\n#journal #work\n"
+    );
+    assert_eq!(
+        expand("{{weekday}} {{time}} by {{user}}", &context),
+        "Monday 14:05 by alice"
+    );
+    assert_eq!(
+        with_title(DEFAULT_DAILY_NOTE, "Plans"),
+        "# Plans\n\n\n{{tags}}\n"
+    );
+    assert_eq!(
+        with_title(DEFAULT_NEW_PAGE, "Plans"),
+        "# Plans\n\n\n\n{{tags}}"
+    );
+    assert_eq!(
+        from_page_body("# Meeting {{date}}\n\n#meeting #template #templates\n"),
+        "# Meeting {{date}}\n\n#meeting #templates\n"
+    );
+}
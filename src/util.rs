@@ -1,5 +1,8 @@
+use anyhow::{bail, Result};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
 use rand::distributions::Alphanumeric;
 use rand::Rng;
+use std::collections::HashMap;
 
 pub fn random_string(len: usize) -> String {
     rand::thread_rng()
@@ -8,8 +11,90 @@ pub fn random_string(len: usize) -> String {
         .collect()
 }
 
+/// Characters that need escaping in a path segment
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?');
+
+pub fn encode_path_segment(s: &str) -> String {
+    utf8_percent_encode(s, PATH_SEGMENT).to_string()
+}
+
+pub fn decode_path_segment(s: &str) -> String {
+    percent_decode_str(s).decode_utf8_lossy().into_owned()
+}
+
 /// Now with a fixed offset of the current system timezone
 pub fn now() -> chrono::DateTime<chrono::offset::FixedOffset> {
     let date = chrono::offset::Local::now();
-    date.with_timezone(&date.offset())
+    date.with_timezone(date.offset())
+}
+
+/// Make sure a path component coming from the outside can't escape its directory
+pub fn check_path_component(s: &str) -> Result<()> {
+    if s.is_empty() || s.starts_with('.') || s.contains('/') || s.contains('\\') {
+        bail!("Invalid name: {}", s);
+    }
+    Ok(())
+}
+
+/// Escape text for use in XML (or HTML) content and attribute values
+pub fn escape_xml(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Today's date, in the local timezone
+pub fn today() -> chrono::NaiveDate {
+    chrono::offset::Local::today().naive_local()
+}
+
+/// Local date of a point in time
+pub fn local_date(time: chrono::DateTime<chrono::offset::FixedOffset>) -> chrono::NaiveDate {
+    time.with_timezone(&chrono::offset::Local)
+        .date()
+        .naive_local()
+}
+
+/// One-line summary of changes between two versions of a text, eg. `+2 -1 lines`
+pub fn line_diff_summary(old: &str, new: &str) -> String {
+    // lines are compared as multisets, so moved lines don't count as changes
+    let mut counts: HashMap<&str, i64> = HashMap::new();
+    for line in old.lines() {
+        *counts.entry(line).or_default() -= 1;
+    }
+    for line in new.lines() {
+        *counts.entry(line).or_default() += 1;
+    }
+    let added: i64 = counts.values().filter(|c| **c > 0).sum();
+    let removed: i64 = -counts.values().filter(|c| **c < 0).sum::<i64>();
+
+    if added == 0 && removed == 0 {
+        "no changes".into()
+    } else {
+        format!("+{} -{} lines", added, removed)
+    }
+}
+
+#[test]
+fn line_diff_summary_test() {
+    assert_eq!(line_diff_summary("a\nb\nc\n", "a\nc\nb\n"), "no changes");
+    assert_eq!(line_diff_summary("a\nb\n", "a\nB\nc\n"), "+2 -1 lines");
+    assert_eq!(line_diff_summary("", "a\n"), "+1 -0 lines");
 }
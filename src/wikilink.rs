@@ -0,0 +1,330 @@
+//! `[[Page Title]]` / `[[id:xyz]]` / `[[Page Title#Section]]` links and `![[Page Title]]` embeds
+//!
+//! Resolving them needs the whole wiki, so it's done in two steps:
+//!
+//! * when parsing markdown, they are replaced with placeholder HTML, that works on its own
+//!   (links go through `/_link`, which resolves them on click)
+//! * before displaying a page, placeholders are resolved against the `Index`,
+//!   marking dangling links, and replacing embeds with the content of embedded pages
+
+use crate::index::Index;
+use crate::page::{self, StoreMut};
+use crate::util::escape_xml;
+use anyhow::Result;
+use futures::future::{BoxFuture, FutureExt};
+use lazy_static::lazy_static;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use pulldown_cmark::{CowStr, Event, Tag};
+
+/// Prefix of link targets given by page ID, instead of title
+pub const ID_PREFIX: &str = "id:";
+
+/// How deep can embedded pages embed other pages
+const MAX_EMBED_DEPTH: usize = 5;
+
+fn encode_target(target: &str) -> String {
+    utf8_percent_encode(target, NON_ALPHANUMERIC).to_string()
+}
+
+fn decode_target(target: &str) -> String {
+    percent_decode_str(target).decode_utf8_lossy().into_owned()
+}
+
+lazy_static! {
+    static ref RE: regex::Regex =
+        regex::Regex::new(r"(!?)\[\[([^\[\]|\n]+)(?:\|([^\[\]\n]+))?\]\]").expect("correct regex");
+}
+
+/// URL resolving (and redirecting to) the target of a link
+pub fn link_url(target: &str) -> String {
+    format!("/_link?to={}", encode_target(target))
+}
+
+/// Split `Page Title#Section` into the page target, and the `#section` anchor (if any)
+///
+/// `#` starts a section only right after a word, as `#tag`s are a part of some titles.
+fn split_section(target: &str) -> (&str, Option<String>) {
+    let pos = target.char_indices().skip(1).find(|&(pos, ch)| {
+        ch == '#'
+            && !target[..pos].ends_with(char::is_whitespace)
+            && target[pos + 1..].starts_with(|ch: char| !ch.is_whitespace())
+    });
+    match pos {
+        Some((pos, _)) => (
+            &target[..pos],
+            Some(format!("#{}", page::heading_slug(&target[pos + 1..]))),
+        ),
+        None => (target, None),
+    }
+}
+
+/// Text with wiki links replaced by their labels, eg. for headings
+pub fn plain_text(text: &str) -> String {
+    RE.replace_all(text, |cap: &regex::Captures| {
+        cap.get(3)
+            .map(|m| m.as_str())
+            .unwrap_or(&cap[2])
+            .trim()
+            .to_owned()
+    })
+    .into_owned()
+}
+
+/// Replace wiki links in a text with placeholder HTML
+fn expand_text<'a>(text: &str, events: &mut Vec<Event<'a>>) {
+    let mut last = 0;
+    for cap in RE.captures_iter(text) {
+        let whole = cap.get(0).expect("be there");
+        if last < whole.start() {
+            events.push(Event::Text(CowStr::from(
+                text[last..whole.start()].to_owned(),
+            )));
+        }
+        last = whole.end();
+
+        let label = cap
+            .get(3)
+            .map(|m| m.as_str().trim())
+            .unwrap_or(cap[2].trim());
+        let (target, section) = split_section(cap[2].trim());
+        let link = format!(
+            r#"<a class="wiki-link" data-target="{}" href="{}{}">{}</a>"#,
+            encode_target(target),
+            link_url(target),
+            section.as_deref().unwrap_or(""),
+            escape_xml(label)
+        );
+        events.push(Event::Html(CowStr::from(if cap[1].is_empty() {
+            link
+        } else {
+            format!(
+                r#"<div class="wiki-embed" data-target="{}">{}</div>"#,
+                encode_target(target),
+                link
+            )
+        })));
+    }
+    if last < text.len() {
+        events.push(Event::Text(CowStr::from(text[last..].to_owned())));
+    }
+}
+
+/// Do the `events` end with a paragraph containing only an embed
+fn is_lone_embed(events: &[Event]) -> bool {
+    match events {
+        [.., Event::Start(Tag::Paragraph), Event::Html(html)] => {
+            html.starts_with(r#"<div class="wiki-embed""#)
+        }
+        _ => false,
+    }
+}
+
+/// Replace wiki links in markdown events with placeholder HTML
+///
+/// Parser splits text on brackets, so consecutive text events are joined first.
+/// Code blocks are left alone.
+pub fn expand_events<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let mut out = vec![];
+    let mut text = String::new();
+    let mut in_code_block = false;
+
+    for event in events {
+        match event {
+            Event::Text(t) if !in_code_block => {
+                text.push_str(&t);
+                continue;
+            }
+            _ => {}
+        }
+        if !text.is_empty() {
+            expand_text(&text, &mut out);
+            text.clear();
+        }
+        match &event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            // embedded page content can't go inside `<p>`
+            Event::End(Tag::Paragraph) if is_lone_embed(&out) => {
+                out.remove(out.len() - 2);
+                continue;
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+    if !text.is_empty() {
+        expand_text(&text, &mut out);
+    }
+    out
+}
+
+/// Render a page, and resolve placeholders in its HTML
+pub async fn resolve<T>(
+    index: &Index<T>,
+    page: &page::Parsed,
+    config: &page::RenderConfig,
+) -> Result<page::Rendered>
+where
+    T: StoreMut + Send + Sync,
+{
+    let rendered = page.render_blocking(config).await?;
+    Ok(page::Rendered {
+        html: resolve_inner(index, rendered.html, vec![page.id().to_owned()], config).await?,
+        ..rendered
+    })
+}
+
+/// `stack` is the list of pages being embedded into each other, to detect cycles
+fn resolve_inner<'a, T>(
+    index: &'a Index<T>,
+    html: String,
+    stack: Vec<page::Id>,
+    config: &'a page::RenderConfig,
+) -> BoxFuture<'a, Result<String>>
+where
+    T: StoreMut + Send + Sync,
+{
+    async move {
+        lazy_static! {
+            static ref EMBED_RE: regex::Regex = regex::Regex::new(
+                r#"<div class="wiki-embed" data-target="([^"]*)">(<a [^>]*>[^<]*</a>)</div>"#
+            )
+            .expect("correct regex");
+            static ref LINK_RE: regex::Regex = regex::Regex::new(
+                r##"<a class="wiki-link" data-target="([^"]*)" href="[^"#]*(#[^"]*)?">"##
+            )
+            .expect("correct regex");
+        }
+
+        // collected upfront, as regex captures can't be held across `await`s
+        let embeds: Vec<_> = EMBED_RE
+            .captures_iter(&html)
+            .map(|cap| {
+                let whole = cap.get(0).expect("be there");
+                (
+                    whole.start(),
+                    whole.end(),
+                    cap[1].to_owned(),
+                    cap[2].to_owned(),
+                )
+            })
+            .collect();
+
+        let mut out = String::new();
+        let mut last = 0;
+        for (start, end, encoded_target, link) in embeds {
+            out.push_str(&html[last..start]);
+            last = end;
+
+            let target = decode_target(&encoded_target);
+            match index.find_by_link_target(&target) {
+                Some(info) if stack.contains(&info.id) => {
+                    out.push_str(&format!(
+                        r#"<div class="wiki-embed wiki-embed-error">Embedding cycle: {}</div>"#,
+                        link
+                    ));
+                }
+                Some(_) if MAX_EMBED_DEPTH <= stack.len() => {
+                    out.push_str(&format!(
+                        r#"<div class="wiki-embed wiki-embed-error">Embedded too deep: {}</div>"#,
+                        link
+                    ));
+                }
+                Some(info) => {
+                    let embedded = index.get(info.id.clone()).await?;
+                    let mut stack = stack.clone();
+                    stack.push(info.id.clone());
+                    let embedded_html = resolve_inner(
+                        index,
+                        embedded.render_blocking(config).await?.html,
+                        stack,
+                        config,
+                    )
+                    .await?;
+                    out.push_str(&format!(
+                        r#"<div class="wiki-embed" data-target="{}">{}</div>"#,
+                        encoded_target, embedded_html
+                    ));
+                }
+                // left as a (dangling) link
+                None => out.push_str(&html[start..end]),
+            }
+        }
+        out.push_str(&html[last..]);
+
+        Ok(LINK_RE
+            .replace_all(&out, |cap: &regex::Captures| {
+                let target = decode_target(&cap[1]);
+                let section = cap.get(2).map(|m| m.as_str()).unwrap_or("");
+                if let Some(info) = index.find_by_link_target(&target) {
+                    format!(
+                        r#"<a class="wiki-link" data-target="{}" href="/?id={}{}">"#,
+                        &cap[1],
+                        // IDs come from page files
+                        utf8_percent_encode(&info.id, NON_ALPHANUMERIC),
+                        section
+                    )
+                } else {
+                    format!(
+                        r#"<a class="wiki-link dangling" data-target="{}" href="{}{}">"#,
+                        &cap[1],
+                        link_url(&target),
+                        section
+                    )
+                }
+            })
+            .into_owned())
+    }
+    .boxed()
+}
+
+#[test]
+fn expand_events_test() {
+    let markdown =
+        "See [[Other page]] and [[id:abc|this]].\n\n![[Embedded]]\n\n```\n[[not a link]]\n```\n";
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(
+        &mut html,
+        expand_events(pulldown_cmark::Parser::new(markdown)).into_iter(),
+    );
+    assert_eq!(
+        html,
+        "<p>See <a class=\"wiki-link\" data-target=\"Other%20page\" href=\"/_link?to=Other%20page\">Other page</a> \
+         and <a class=\"wiki-link\" data-target=\"id%3Aabc\" href=\"/_link?to=id%3Aabc\">this</a>.</p>\n\
+         <div class=\"wiki-embed\" data-target=\"Embedded\"><a class=\"wiki-link\" data-target=\"Embedded\" href=\"/_link?to=Embedded\">Embedded</a></div>\n\
+         <pre><code>[[not a link]]\n</code></pre>\n"
+    );
+}
+
+#[tokio::test]
+async fn resolve_test() -> Result<()> {
+    let mut index = Index::new(page::store::InMemoryStore::new()).await?;
+    for (id, body) in &[
+        (
+            "a",
+            "# Page A\n\n![[Page B]] [[Nowhere]] [[Page B#Step 2]] [[Page C]]",
+        ),
+        ("b", "# Page B\n\nB embeds ![[id:a]]"),
+        ("c\"><script>", "# Page C"),
+    ] {
+        index
+            .put(&page::Parsed::new_with_id(id.to_string(), body))
+            .await?;
+    }
+
+    let page_a = index.get("a".into()).await?;
+    let html = resolve(&index, &page_a, &page::RenderConfig::default())
+        .await?
+        .html;
+    assert!(html.contains("B embeds"));
+    assert!(html.contains("Embedding cycle"));
+    assert!(html.contains(
+        r#"<a class="wiki-link dangling" data-target="Nowhere" href="/_link?to=Nowhere">"#
+    ));
+    assert!(html.contains(r#"<a class="wiki-link" data-target="id%3Aa" href="/?id=a">"#));
+    assert!(html.contains(r##"<a class="wiki-link" data-target="Page%20B" href="/?id=b#step-2">"##));
+    assert!(html.contains(
+        r#"<a class="wiki-link" data-target="Page%20C" href="/?id=c%22%3E%3Cscript%3E">"#
+    ));
+    Ok(())
+}